@@ -0,0 +1,141 @@
+extern crate sfml;
+
+pub mod camera {
+    use crate::Map;
+    use crate::Car;
+    use sfml::graphics::{View, RenderWindow, RenderTarget};
+    use sfml::system::Vector2f;
+    use sfml::window::{Event, Key};
+    use sfml::window::mouse::Button;
+
+    // part of the distance to the followed car covered at every frame
+    const SMOOTHING: f32 = 0.15;
+    // wheel notch zoom
+    const ZOOM: f32 = 0.9;
+    // width of the view in pixels of the map
+    const MIN_SIZE: f32 = 64.;
+    const MAX_SIZE: f32 = 4096.;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Mode {
+        Free,
+        // the view moves smoothly to the selected car
+        Follow,
+    }
+
+    // What part of the map the window shows. Drag with the left button to
+    // pan, the wheel zooms around the mouse, F follows the selected car,
+    // Tab selects the next one and 0 fits the view to the track again.
+    pub struct Camera {
+        pub mode: Mode,
+        pub selected: usize,
+        center: Vector2f,
+        size: Vector2f,
+        // rectangle around the walls, (center, size)
+        track: (Vector2f, Vector2f),
+        dragging: Option<(i32, i32)>,
+    }
+
+    impl Camera {
+        pub fn new(map: &Map) -> Camera {
+            let track = track(map);
+            Camera {
+                mode: Mode::Free,
+                selected: 0,
+                center: track.0,
+                size: track.1,
+                track: track,
+                dragging: None,
+            }
+        }
+
+        pub fn fit(&mut self) {
+            self.mode = Mode::Free;
+            self.center = self.track.0;
+            self.size = self.track.1;
+        }
+
+        // pixels of the map in one pixel of the window
+        fn scale(&self, window: &RenderWindow) -> f32 {
+            self.size.x / window.size().x as f32
+        }
+
+        pub fn handle(&mut self, window: &RenderWindow, event: &Event) {
+            match *event {
+                Event::MouseButtonPressed { button: Button::Left, x, y } => {
+                    self.dragging = Some((x, y));
+                },
+                Event::MouseButtonReleased { button: Button::Left, .. } => {
+                    self.dragging = None;
+                },
+                Event::MouseMoved { x, y } => {
+                    if let Some((last_x, last_y)) = self.dragging {
+                        let scale = self.scale(window);
+                        self.mode = Mode::Free;
+                        self.center.x -= (x - last_x) as f32 * scale;
+                        self.center.y -= (y - last_y) as f32 * scale;
+                        self.dragging = Some((x, y));
+                    }
+                },
+                Event::MouseWheelScrolled { delta, x, y, .. } => {
+                    // the point under the mouse stays where it is
+                    let size = window.size();
+                    let offset = Vector2f::new(x as f32 - size.x as f32 / 2., y as f32 - size.y as f32 / 2.);
+                    let scale = self.scale(window);
+                    let factor = ZOOM.powf(delta);
+                    let width = (self.size.x * factor).max(MIN_SIZE).min(MAX_SIZE);
+                    let factor = width / self.size.x;
+                    let point = self.center + offset * scale;
+                    self.size = self.size * factor;
+                    self.center = point - offset * scale * factor;
+                },
+                Event::KeyPressed { code: Key::F, .. } => {
+                    self.mode = if self.mode == Mode::Follow { Mode::Free } else { Mode::Follow };
+                },
+                Event::KeyPressed { code: Key::Tab, .. } => {
+                    self.selected = self.selected + 1;
+                },
+                Event::KeyPressed { code: Key::Num0, .. } => {
+                    self.fit();
+                },
+                _ => {},
+            }
+        }
+
+        // called once per frame, before view()
+        pub fn update(&mut self, cars: &[Car]) {
+            if cars.is_empty() {
+                return;
+            }
+            self.selected = self.selected % cars.len();
+            if self.mode == Mode::Follow {
+                let car = &cars[self.selected];
+                self.center.x += (car.x - self.center.x) * SMOOTHING;
+                self.center.y += (car.y - self.center.y) * SMOOTHING;
+            }
+        }
+
+        pub fn view(&self) -> View {
+            View::new(self.center, self.size)
+        }
+    }
+
+    // Smallest square around the walls, with a margin.
+    fn track(map: &Map) -> (Vector2f, Vector2f) {
+        let (mut min, mut max) = ((256, 256), (0, 0));
+        for a in 0..256 {
+            for b in 0..256 {
+                if map.core[a][b] == 1 {
+                    min = (min.0.min(a), min.1.min(b));
+                    max = (max.0.max(a + 1), max.1.max(b + 1));
+                }
+            }
+        }
+        if min.0 >= max.0 {
+            return (Vector2f::new(512., 512.), Vector2f::new(1024., 1024.));
+        }
+        let center = Vector2f::new((min.0 + max.0) as f32 * 2., (min.1 + max.1) as f32 * 2.);
+        let side = ((max.0 - min.0).max(max.1 - min.1) * 4) as f32 + 32.;
+        (center, Vector2f::new(side, side))
+    }
+}
@@ -4,9 +4,17 @@ extern crate nalgebra as na;
 pub mod car {
     use crate::Map;
     use sfml::graphics::{Color, Transformable, RenderWindow, RenderTarget,
-                        RectangleShape, Sprite, Texture, Shape};
+                        RectangleShape, Shape};
+    use crate::Controller;
     use crate::Model;
-    use na::DMatrix;
+    use crate::model::model::Scratch;
+    use na::{DMatrix, Real};
+
+    // size of Car::observation, the input of the models
+    pub const OBSERVATION_SIZE: u32 = 58;
+    // size of Car::radar_grid, 8 rows of 7
+    pub const RADAR_SIZE: u32 = 56;
+
     pub struct Car {
         pub x: f32,
         pub y: f32,
@@ -98,28 +106,14 @@ pub mod car {
             }
         }
 
-        pub fn render(&self, window: &mut RenderWindow) {
-            let mut rect_car = RectangleShape::new();
-            rect_car.set_size((32.0 , 16.0));
-            rect_car.set_origin((16., 8.));
-            rect_car.set_rotation(-self.angle * (180.0 / 3.1415));
-            rect_car.set_position((self.x, self.y));
-            rect_car.set_fill_color(&Color::rgba(0, 255, 0, 255));
-            window.draw(&rect_car);
-
-
-            let texture = Texture::from_file("resource/car.png").unwrap();
-            let mut sprite = Sprite::new();
-            sprite.set_texture(&texture, true);
-            sprite.set_origin((256., 128.));
-            sprite.scale((0.0625, 0.0625));
-            sprite.set_rotation(-self.angle * (180.0 / 3.1415));
-            sprite.set_position((self.x, self.y));
-            window.draw(&sprite);
-
-
-
-            // self.render_radar(window);
+        // corners of the 32 x 16 body, clockwise from the rear left one
+        pub fn corners(&self) -> [(f32, f32); 4] {
+            let (sin, cos) = (-self.angle).sin_cos();
+            let mut r = [(0., 0.); 4];
+            for (i, (x, y)) in [(-16., -8.), (16., -8.), (16., 8.), (-16., 8.)].iter().enumerate() {
+                r[i] = (self.x + x * cos - y * sin, self.y + x * sin + y * cos);
+            }
+            return r;
         }
 
         pub fn render_radar(&self, window: &mut RenderWindow) {
@@ -180,17 +174,67 @@ pub mod car {
             }
         }
 
-        pub fn input(&mut self, model: &Model) {
-            let mut input: DMatrix<f64> = DMatrix::new_random(58, 1);
-            for (i, a) in input.iter_mut().enumerate() {
-                *a = self.radar[i/8][i%7] as f64;
+        // Value i of what a model reads: the radar row by row for the grid,
+        // the historic indexing of the 58 values of the observation otherwise.
+        fn sensor(&self, grid: bool, i: usize) -> f64 {
+            if grid {
+                self.radar[i / 7][i % 7] as f64
+            } else {
+                self.radar[i / 8][i % 7] as f64
             }
-            let result = model.get_result(&input);
-            self.a = *result.get((0, 0)).unwrap() as f32;
-            self.steering = (*result.get((1, 0)).unwrap() / 50.) as f32;
+        }
+
+        pub fn observation(&self) -> DMatrix<f64> {
+            DMatrix::from_fn(OBSERVATION_SIZE as usize, 1, |i, _| self.sensor(false, i))
+        }
+
+        // the radar row by row, for the models starting with a convolution
+        pub fn radar_grid(&self) -> DMatrix<f64> {
+            DMatrix::from_fn(RADAR_SIZE as usize, 1, |i, _| self.sensor(true, i))
+        }
+
+        // what a model reads: the radar grid if it asks for it, the observation otherwise
+        pub fn observe<C: Controller>(&self, model: &C) -> DMatrix<f64> {
+            if model.input_size() == Some(RADAR_SIZE) {
+                self.radar_grid()
+            } else {
+                self.observation()
+            }
+        }
+
+        // Same as observe, written into `out`, only allocates when `out`
+        // does not have the right size.
+        pub fn observe_into<C: Controller, N: Real>(&self, model: &C, out: &mut DMatrix<N>) {
+            let grid = model.input_size() == Some(RADAR_SIZE);
+            let size = if grid { RADAR_SIZE } else { OBSERVATION_SIZE } as usize;
+            if out.shape() != (size, 1) {
+                *out = DMatrix::zeros(size, 1);
+            }
+            for (i, a) in out.iter_mut().enumerate() {
+                *a = na::convert(self.sensor(grid, i));
+            }
+        }
+
+        // apply an output of a model: [throttle, steering]
+        pub fn set_action(&mut self, throttle: f64, steering: f64) {
+            self.a = throttle as f32;
+            self.steering = (steering / 50.) as f32;
+        }
+
+        pub fn input<C: Controller>(&mut self, model: &C) {
+            let result = model.get_result(&self.observe(model));
+            self.set_action(*result.get((0, 0)).unwrap(), *result.get((1, 0)).unwrap());
             // println!("a={}, steering={}", self.a, self.steering);
         }
 
+        // Same as input with buffers kept between two steps.
+        pub fn input_with<N: Real>(&mut self, model: &Model<N>, observation: &mut DMatrix<N>, scratch: &mut Scratch<N>)
+            where Model<N>: Controller {
+            self.observe_into(model, observation);
+            let result = model.get_result_with(observation, scratch);
+            self.set_action(na::try_convert(result[(0, 0)]).unwrap(), na::try_convert(result[(1, 0)]).unwrap());
+        }
+
         pub fn collide(&self, map: &Map) -> bool {
             let mut rect_car = RectangleShape::new();
             rect_car.set_size((16.0 + (self.angle.cos() * 16.0).abs(),
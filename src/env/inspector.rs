@@ -0,0 +1,114 @@
+extern crate sfml;
+extern crate nalgebra as na;
+
+pub mod inspector {
+    use crate::Model;
+    use sfml::graphics::{Color, Transformable, RenderWindow, RenderTarget,
+                        RectangleShape, CircleShape, Shape, VertexArray, Vertex, PrimitiveType};
+
+    // Panel drawing a network as a graph: one column of neurons per layer,
+    // the inputs on the left. The neurons are coloured by the values recorded
+    // at the last get_result, yellow above zero and blue below; the edges
+    // are green for a positive weight and red for a negative one, more
+    // opaque as the weight grows.
+    pub struct Inspector {
+        pub x: f32,
+        pub y: f32,
+        pub width: f32,
+        pub height: f32,
+        pub visible: bool,
+    }
+
+    const MARGIN: f32 = 12.;
+
+    impl Inspector {
+        pub fn new(x: f32, y: f32, width: f32, height: f32) -> Inspector {
+            Inspector {
+                x: x,
+                y: y,
+                width: width,
+                height: height,
+                visible: true,
+            }
+        }
+
+        fn position(&self, column: usize, columns: usize, i: usize, n: usize) -> (f32, f32) {
+            let dx = (self.width - 2. * MARGIN) / (columns - 1).max(1) as f32;
+            let dy = (self.height - 2. * MARGIN) / n.max(1) as f32;
+            (self.x + MARGIN + column as f32 * dx, self.y + MARGIN + (i as f32 + 0.5) * dy)
+        }
+
+        // The model must record, see Model::record.
+        pub fn render(&self, window: &mut RenderWindow, model: &Model) {
+            let values = match model.recorded() {
+                Some(v) => v,
+                None => return,
+            };
+            let layers = model.layers();
+            if !self.visible || values.len() != layers.len() + 1 {
+                return;
+            }
+            let mut background = RectangleShape::new();
+            background.set_size((self.width, self.height));
+            background.set_position((self.x, self.y));
+            background.set_fill_color(&Color::rgba(255, 255, 255, 220));
+            background.set_outline_color(&Color::BLACK);
+            background.set_outline_thickness(1.);
+            window.draw(&background);
+
+            let columns = values.len();
+            let mut edges = VertexArray::new(PrimitiveType::Lines, 0);
+            for (l, layer) in layers.iter().enumerate() {
+                // the weights of a convolution are shared, they have no edge
+                let w = layer.params()[0].1;
+                if w.shape() != (layer.neuron() as usize, layer.input() as usize) {
+                    continue;
+                }
+                let max = w.iter().fold(0., |m: f64, v| m.max(v.abs()));
+                if max == 0. {
+                    continue;
+                }
+                for j in 0..w.nrows() {
+                    for i in 0..w.ncols() {
+                        let alpha = (w[(j, i)].abs() / max * 255.) as u8;
+                        if alpha == 0 {
+                            continue;
+                        }
+                        let color = if w[(j, i)] > 0. {
+                            Color::rgba(0, 160, 0, alpha)
+                        } else {
+                            Color::rgba(200, 0, 0, alpha)
+                        };
+                        edges.append(&Vertex::with_pos_color(self.position(l, columns, i, w.ncols()), color));
+                        edges.append(&Vertex::with_pos_color(self.position(l + 1, columns, j, w.nrows()), color));
+                    }
+                }
+            }
+            window.draw(&edges);
+
+            for (c, v) in values.iter().enumerate() {
+                let spacing = (self.height - 2. * MARGIN) / v.nrows().max(1) as f32;
+                let radius = (spacing / 2.5).min(5.).max(1.);
+                let mut neuron = CircleShape::new(radius, 12);
+                neuron.set_origin((radius, radius));
+                neuron.set_outline_color(&Color::BLACK);
+                neuron.set_outline_thickness(if radius > 2. { 1. } else { 0. });
+                for i in 0..v.nrows() {
+                    neuron.set_fill_color(&activation_color(v[(i, 0)]));
+                    neuron.set_position(self.position(c, columns, i, v.nrows()));
+                    window.draw(&neuron);
+                }
+            }
+        }
+    }
+
+    // white at zero, yellow at 1 and above, blue at -1 and below
+    fn activation_color(v: f64) -> Color {
+        let t = (v.abs().min(1.) * 255.) as u8;
+        if v >= 0. {
+            Color::rgb(255, 255, 255 - t)
+        } else {
+            Color::rgb(255 - t, 255 - t, 255)
+        }
+    }
+}
@@ -2,13 +2,12 @@ extern crate sfml;
 extern crate image;
 
 pub mod map {
-    use sfml::graphics::{Color, Transformable, RenderWindow, RenderTarget,
-                    RectangleShape, Shape};
+    use sfml::graphics::{Color, PrimitiveType, Vertex, VertexArray};
     use image::GenericImageView;
     use std::path::Path;
+    #[derive(Clone)]
     pub struct Map {
         pub core: [[u64; 256]; 256],
-        pub level: Level,
     }
 
     #[derive(Copy, Clone)]
@@ -31,7 +30,7 @@ pub mod map {
                     image::open(&Path::new("resource/level3.png")).unwrap()
                 }
             };
-            let mut map = Map { core: [[0; 256]; 256], level: level};
+            let mut map = Map { core: [[0; 256]; 256] };
             let (width, height) = img.dimensions();
             if width == 256 && height == 256 {
                 for x in 0..width {
@@ -60,18 +59,20 @@ pub mod map {
             }
         }
 
-        pub fn render(&self, window: &mut RenderWindow) {
+        // One red quad per wall cell, built once and drawn in a single call.
+        pub fn walls(&self) -> VertexArray {
+            let mut walls = VertexArray::new(PrimitiveType::Quads, 0);
             for a in 0..256 {
                 for b in 0..256 {
                     if self.core[a][b] == 1 {
-                        let mut rect = RectangleShape::new();
-                        rect.set_size((4.0, 4.0));
-                        rect.set_position((a as f32 * 4.0, b as f32 * 4.0));
-                        rect.set_fill_color(&Color::RED);
-                        window.draw(&rect);
+                        let (x, y) = (a as f32 * 4.0, b as f32 * 4.0);
+                        for (dx, dy) in [(0., 0.), (4., 0.), (4., 4.), (0., 4.)].iter() {
+                            walls.append(&Vertex::with_pos_color((x + dx, y + dy), Color::RED));
+                        }
                     }
                 }
             }
+            return walls;
         }
     }
 }
\ No newline at end of file
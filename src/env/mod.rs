@@ -1,2 +1,6 @@
+pub(crate) mod camera;
 pub(crate) mod car;
-pub(crate) mod map;
\ No newline at end of file
+pub(crate) mod inspector;
+pub(crate) mod map;
+pub(crate) mod playback;
+pub(crate) mod renderer;
\ No newline at end of file
@@ -0,0 +1,71 @@
+extern crate sfml;
+
+pub mod playback {
+    use sfml::window::Key;
+
+    pub const MIN_SPEED: f32 = 0.25;
+    pub const MAX_SPEED: f32 = 16.;
+
+    // How fast the viewers run the physics. Space pauses and resumes, N
+    // steps one tick while paused, + and - double or halve the speed.
+    pub struct Playback {
+        pub paused: bool,
+        // physics ticks per frame
+        pub speed: f32,
+        // ticks not played yet, below one
+        budget: f32,
+        steps: usize,
+    }
+
+    impl Playback {
+        pub fn new() -> Playback {
+            Playback {
+                paused: false,
+                speed: 1.,
+                budget: 0.,
+                steps: 0,
+            }
+        }
+
+        // Returns true when R asks to restart the episode.
+        pub fn handle(&mut self, keys: &[Key]) -> bool {
+            let mut restart = false;
+            for key in keys.iter() {
+                match *key {
+                    Key::Space => {
+                        self.paused = !self.paused;
+                        println!("{}", if self.paused { "paused" } else { "resumed" });
+                    },
+                    Key::N => {
+                        self.paused = true;
+                        self.steps = self.steps + 1;
+                    },
+                    Key::Add | Key::Equal => {
+                        self.speed = (self.speed * 2.).min(MAX_SPEED);
+                        println!("speed x{}", self.speed);
+                    },
+                    Key::Subtract | Key::Dash => {
+                        self.speed = (self.speed / 2.).max(MIN_SPEED);
+                        println!("speed x{}", self.speed);
+                    },
+                    Key::R => restart = true,
+                    _ => {},
+                }
+            }
+            return restart;
+        }
+
+        // number of physics ticks to play before the next frame
+        pub fn ticks(&mut self) -> usize {
+            if self.paused {
+                let r = self.steps;
+                self.steps = 0;
+                return r;
+            }
+            self.budget += self.speed;
+            let r = self.budget.floor();
+            self.budget -= r;
+            return r as usize;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+extern crate sfml;
+
+pub mod renderer {
+    use crate::Map;
+    use crate::Car;
+    use crate::env::camera::camera::Camera;
+    use sfml::graphics::{Color, RenderWindow, RenderTarget, RenderStates,
+                        Texture, PrimitiveType, Vertex, VertexArray, View};
+    use sfml::system::Vector2f;
+
+    // Everything the viewer draws, the assets are loaded once: the walls of
+    // the map are baked in a vertex array, the bodies and the sprites of all
+    // the cars are drawn with one call each.
+    pub struct Renderer {
+        pub camera: Camera,
+        walls: VertexArray,
+        car: Texture,
+        bodies: VertexArray,
+        sprites: VertexArray,
+    }
+
+    impl Renderer {
+        pub fn new(map: &Map) -> Renderer {
+            Renderer {
+                camera: Camera::new(map),
+                walls: map.walls(),
+                car: Texture::from_file("resource/car.png").expect("can not load resource/car.png"),
+                bodies: VertexArray::new(PrimitiveType::Quads, 0),
+                sprites: VertexArray::new(PrimitiveType::Quads, 0),
+            }
+        }
+
+        pub fn render_map(&self, window: &mut RenderWindow) {
+            window.draw(&self.walls);
+        }
+
+        pub fn render_cars(&mut self, window: &mut RenderWindow, cars: &[Car]) {
+            let size = self.car.size();
+            let (w, h) = (size.x as f32, size.y as f32);
+            let texture = [Vector2f::new(0., 0.), Vector2f::new(w, 0.), Vector2f::new(w, h), Vector2f::new(0., h)];
+            self.bodies.clear();
+            self.sprites.clear();
+            for car in cars.iter() {
+                for (corner, t) in car.corners().iter().zip(texture.iter()) {
+                    self.bodies.append(&Vertex::with_pos_color(*corner, Color::rgba(0, 255, 0, 255)));
+                    self.sprites.append(&Vertex::new(*corner, Color::WHITE, *t));
+                }
+            }
+            window.draw(&self.bodies);
+            let states = RenderStates {
+                texture: Some(&self.car),
+                ..Default::default()
+            };
+            window.draw_with_renderstates(&self.sprites, states);
+        }
+
+        // The map then the cars, seen by the camera. The view of the window
+        // is the whole window again after, for the panels drawn on top.
+        pub fn render(&mut self, window: &mut RenderWindow, cars: &[Car]) {
+            self.camera.update(cars);
+            window.set_view(&self.camera.view());
+            self.render_map(window);
+            self.render_cars(window, cars);
+            let size = window.size();
+            let (w, h) = (size.x as f32, size.y as f32);
+            window.set_view(&View::new(Vector2f::new(w / 2., h / 2.), Vector2f::new(w, h)));
+        }
+    }
+}
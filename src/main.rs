@@ -2,48 +2,89 @@ extern crate sfml;
 extern crate image;
 extern crate nalgebra as na;
 extern crate rand;
+#[macro_use]
+extern crate lazy_static;
 
 use rand::Rng;
 
 mod model;
 mod env;
+mod train;
 
-use model::model::{Model, Layer, Activation};
+use model::model::{Model, Layer, Activation, Controller, Init, Kind, Conv, register_activation};
+use model::neat::neat::{Genome, Innovations};
+use model::save::save;
+use model::grad::grad::{Dataset, Optimizer};
+use model::quant::quant;
+use model::codegen::codegen::{self, Language};
 use env::map::map::{Map, Level};
-use env::car::car::{Car};
+use env::car::car::{Car, OBSERVATION_SIZE, RADAR_SIZE};
+use env::inspector::inspector::Inspector;
+use env::renderer::renderer::Renderer;
+use env::playback::playback::Playback;
+use train::pool::pool::{self, Pool};
+use train::species::species;
+use train::novelty::novelty::{self, Characterization};
+use train::es::es::{OpenAiEs, CmaEs};
+use train::cloning::cloning;
+use train::curve::curve::Curve;
+use train::rl::rl::{self, PolicyGradient};
+use train::dqn::dqn::{self, Dqn};
+use train::pruning::pruning;
 use sfml::graphics::{Color, RenderWindow, RenderTarget, RectangleShape};
 use sfml::window::{Event, Key, Style};
 
-use std::sync::mpsc;
+use na::DVector;
+use std::sync::Arc;
 use std::{thread, time};
 
-fn get_event(window: &mut RenderWindow) -> bool{
+// maximal distance between two models of the same species
+const SPECIES_THRESHOLD: f64 = 0.1;
+// where the best model of a training is saved
+const BEST_MODEL: &str = "best.model";
+const STUDENT_MODEL: &str = "student.model";
+const PRUNED_MODEL: &str = "pruned.model";
+// where the quantize mode exports the int8 model
+const QUANTIZED_MODEL: &str = "quantized.model";
+// where the export mode writes the generated code
+const EXPORT_DIR: &str = "export";
+// largest difference accepted between the generated code and the model
+const EXPORT_TOLERANCE: f64 = 1e-9;
+// where the drive mode records the demonstrations
+const DEMONSTRATIONS: &str = "demonstrations.txt";
+
+// The other events move the camera.
+fn get_event(window: &mut RenderWindow, renderer: &mut Renderer) -> bool{
+    get_events(window, renderer, &mut Vec::new())
+}
+
+// Same as get_event, the other keys pressed since the last call are added
+// to `keys`.
+fn get_events(window: &mut RenderWindow, renderer: &mut Renderer, keys: &mut Vec<Key>) -> bool{
     while let Some(event) = window.poll_event() {
         match event {
             Event::Closed | Event::KeyPressed {
                 code: Key::Escape, ..
             } => return true,
+            Event::KeyPressed { code, .. } => keys.push(code),
             _ => {}
         }
+        renderer.camera.handle(window, &event);
     }
     return false;
 }
 
-fn render(window: &mut RenderWindow, map: &Map) {
-    map.render(window);
-}
-
-fn get_best_of_gen_show(window: &mut RenderWindow, map: &Map, models: Vec<Model>) -> Model{
+fn get_best_of_gen_show(window: &mut RenderWindow, renderer: &mut Renderer, map: &Map, models: Vec<Model>) -> Model{
     let mut cars: Vec<Car> = Vec::new();
     let mut n = 0;
 
-    for _ in models.iter() {
+    for m in models.iter() {
         cars.push(Car::new(500.0, 500., 0.0));
+        m.reset_state();
     }
     loop {
-        if get_event(window) {
-            panic!();
-        }
+        //Escape stops the generation early
+        let quit = get_event(window, renderer);
         for (i, a) in cars.iter_mut().enumerate() {
             a.input(models.get(i).unwrap());
             a.update(&map);
@@ -55,7 +96,7 @@ fn get_best_of_gen_show(window: &mut RenderWindow, map: &Map, models: Vec<Model>
                 vie = true;
             }
         }
-        if !vie || n > 200 {
+        if quit || !vie || n > 200 {
             println!("tout est mort ou on a fini: n={}", n);
             let mut best = 0;
             for (i, a) in cars.iter().enumerate() {
@@ -66,10 +107,7 @@ fn get_best_of_gen_show(window: &mut RenderWindow, map: &Map, models: Vec<Model>
             return models.get(best).unwrap().clone();
         }
         window.clear(&Color::WHITE);
-        for a in cars.iter_mut() {
-            a.render(window);
-        }
-        render(window, &map);
+        renderer.render(window, &cars);
         window.display();
         n = n + 1;
         //println!(" n={}", n);
@@ -80,8 +118,9 @@ fn get_best_of_gen(map: &Map, models: Vec<Model>) -> Model{
     let mut cars: Vec<Car> = Vec::new();
     let mut n = 0;
 
-    for _ in models.iter() {
+    for m in models.iter() {
         cars.push(Car::new(500.0, 500., 0.0));
+        m.reset_state();
     }
     loop {
         for (i, a) in cars.iter_mut().enumerate() {
@@ -109,93 +148,91 @@ fn get_best_of_gen(map: &Map, models: Vec<Model>) -> Model{
     }
 }
 
-fn get_n_best_of_gen(map: &Map, models: Vec<Model>, num: usize) -> Vec<(Model, usize)>{
-    let mut cars: Vec<Car> = Vec::new();
+// Plays an episode of the controller, returns true when the window is closed
+// or Escape is pressed and false when the car crashes. R restarts the
+// episode, I shows or hides the activations of the network when the
+// controller has one.
+fn show_model<C: Controller>(window: &mut RenderWindow, renderer: &mut Renderer, playback: &mut Playback,
+                             map: &Map, model: &C) -> bool {
+    let mut car: Car = Car::new(500.0, 500., 0.0);
     let mut n = 0;
-    let mut my_models: Vec<(Model, usize)> = Vec::new();
-    for _ in models.iter() {
-        cars.push(Car::new(500.0, 500., 0.0));
+    let mut inspector = Inspector::new(624., 0., 400., 300.);
+    let network = model.network();
+    model.reset_state();
+    if let Some(network) = network {
+        network.record(true);
     }
 
-    for m in models {
-        my_models.push((m, 0));
-    }
-
-    loop {
-        for (i, a) in cars.iter_mut().enumerate() {
-            a.input(&my_models.get(i).unwrap().0);
-            a.update(&map);
+    let quit = loop {
+        let mut keys = Vec::new();
+        if get_events(window, renderer, &mut keys) {
+            break true;
         }
-        
-        let mut vie = false;
-        for a in cars.iter() {
-            if a.alive && a.v >= 0.1 {
-                vie = true;
-            }
+        if keys.contains(&Key::I) {
+            inspector.visible = !inspector.visible;
         }
-        if !vie || n > 500 {
-            // while models.len() > num {
-            //     let mut worst = 0;
-            //     for (i, a) in cars.iter().enumerate() {
-            //         if a.d <= cars.get(worst).unwrap().d {
-            //             worst = i;
-            //         }
-            //     }
-            //     cars.remove(worst);
-            //     models.remove(worst);
-            // }
-            for (i, a) in cars.iter().enumerate() {
-                my_models[i].1 = a.d as usize;
-            }
-
-            my_models.sort_by(|a, b| b.1.cmp(&a.1));
-            my_models.truncate(num);
-    
-            //println!("tout est mort ou on a fini: n={}, worst={} ,best={}", n, worst, best);
-            return my_models;
+        if playback.handle(&keys) {
+            car = Car::new(500.0, 500., 0.0);
+            model.reset_state();
+            n = 0;
         }
-        n = n + 1;
-    }
-}
-
-fn show_model(window: &mut RenderWindow, map: &Map, model: &Model) {
-    let mut car: Car = Car::new(500.0, 500., 0.0);
-    let mut n = 0;
-
-    loop {
-        if get_event(window) {
-            panic!();
+        for _ in 0..playback.ticks() {
+            car.input(model);
+            car.update(map);
+            n = n + 1;
+            if !car.alive {
+                break;
+            }
         }
-        car.input(model);
-        car.update(map);
         
         let vie = car.alive;
         if !vie {
-            return;
+            break false;
         }
         window.clear(&Color::WHITE);
-        render(window, &map);
-        car.render(window);
+        renderer.render(window, std::slice::from_ref(&car));
+        if let Some(network) = network {
+            inspector.render(window, network);
+        }
         window.display();
-        n = n + 1;
+    };
+    if let Some(network) = network {
+        network.record(false);
     }
+    println!("end of the episode: n={}, d={}", n, car.d);
+    return quit;
 }
 
-fn show_models(window: &mut RenderWindow, map: &Map, models: &Vec<Model>) {
+// Same as show_model with several cars, the episode ends when they are all
+// dead.
+fn show_models(window: &mut RenderWindow, renderer: &mut Renderer, playback: &mut Playback,
+               map: &Map, models: &Vec<Model>) -> bool {
     let mut cars: Vec<Car> = Vec::new();
     let mut n = 0;
 
-    for _ in models.iter() {
-      cars.push(Car::new(500.0, 500.0, 0.0))  
+    for m in models.iter() {
+      cars.push(Car::new(500.0, 500.0, 0.0));
+      m.reset_state();
     }
     loop {
         let start = std::time::Instant::now();
-        if get_event(window) {
-            panic!();
+        let mut keys = Vec::new();
+        if get_events(window, renderer, &mut keys) {
+            return true;
         }
-        for (i, car) in cars.iter_mut().enumerate() {
-            car.input(models.get(i).unwrap());
-            car.update(map);
+        if playback.handle(&keys) {
+            for (i, car) in cars.iter_mut().enumerate() {
+                *car = Car::new(500.0, 500.0, 0.0);
+                models.get(i).unwrap().reset_state();
+            }
+            n = 0;
+        }
+        for _ in 0..playback.ticks() {
+            for (i, car) in cars.iter_mut().enumerate() {
+                car.input(models.get(i).unwrap());
+                car.update(map);
+            }
+            n = n + 1;
         }
         let mut vie = false;
         for car in cars.iter() {
@@ -204,82 +241,103 @@ fn show_models(window: &mut RenderWindow, map: &Map, models: &Vec<Model>) {
             }
         }
         if !vie {
-            return;
+            println!("end of the episode: n={}", n);
+            return false;
         }
         window.clear(&Color::WHITE);
-        render(window, &map);
-        for car in cars.iter() {
-            car.render(window);
-        }
+        renderer.render(window, &cars);
         window.display();
         let dt = start.elapsed();
-        match std::time::Duration::new(0, 33333333).checked_sub(dt) {
-            Some(t) => {
-                println!("sleep for {:?}", t);
-                thread::sleep(t);
-            },
-            None => {
-                println!("je ne sleep pas {:?}", dt);
-            },
-        };
-        n = n + 1;
+        if let Some(t) = std::time::Duration::new(0, 33333333).checked_sub(dt) {
+            thread::sleep(t);
+        }
+    }
+}
+
+// Drive with the arrow keys and record what the car sees and what the driver
+// does at every step. The car restarts when it crashes, Escape ends the
+// recording and saves it.
+fn drive(window: &mut RenderWindow, renderer: &mut Renderer, map: &Map, path: &str) {
+    let mut car: Car = Car::new(500.0, 500., 0.0);
+    let mut dataset = Dataset::new();
+
+    loop {
+        if get_event(window, renderer) {
+            break;
+        }
+        let mut throttle = 0.;
+        let mut steering = 0.;
+        if Key::Up.is_pressed() {
+            throttle += 0.2;
+        }
+        if Key::Down.is_pressed() {
+            throttle -= 0.2;
+        }
+        if Key::Left.is_pressed() {
+            steering += 1.;
+        }
+        if Key::Right.is_pressed() {
+            steering -= 1.;
+        }
+        let observation = car.observation();
+        car.set_action(throttle, steering);
+        dataset.push(DVector::from_column_slice(observation.as_slice()),
+                     DVector::from_vec(vec![throttle, steering]));
+        car.update(map);
+        if !car.alive {
+            println!("crash after d={}", car.d);
+            car = Car::new(500.0, 500., 0.0);
+        }
+
+        window.clear(&Color::WHITE);
+        renderer.render(window, std::slice::from_ref(&car));
+        window.display();
     }
+    dataset.save(path).expect("can not save the demonstrations");
+    println!("{} steps recorded in {}", dataset.len(), path);
 }
 
-fn train_with_genetic(map: &Map, model: Model) -> Vec<Model> {
+fn train_with_genetic(map: &Map, model: Model, curve: &mut Curve) -> Vec<Model> {
     let mut models: Vec<Model> = Vec::new();
+    let pool = Pool::new_batched(Arc::new(map.clone()), pool::distances);
+    println!("evaluate with {} workers", pool.size());
 
     //initial population init random
     for _ in 0..500 {
         models.push(model.copy_mut(1.0, 3.0));
     }
-    //get the 5 best of the first generation to create more like those
-    let mut bests = get_n_best_of_gen(&map, models, 8);
+    //get the 8 best of the first generation to create more like those
+    let mut bests = pool.evaluate(models);
+    let mean = bests.iter().map(|e| e.1 as f64).sum::<f64>() / bests.len() as f64;
+    let best = bests.iter().map(|e| e.1).max().unwrap_or(0);
+    curve.push(bests.len(), mean, best as f64);
+    bests.sort_by(|a, b| b.1.cmp(&a.1));
+    bests.truncate(8);
 
     //for each generation we get the 8 best and create more like those
     for generation in 1..20 {
         println!("start generation: {}", generation);
-        let mut my_threads = Vec::new();
-        let mut my_rx = Vec::new();
-        let level = map.level;
-
-        let chunk = bests.chunks(bests.len() / 8);
-        for best in bests {
-            let (tx, rx) = mpsc::channel();
-            my_rx.push(rx);
-            my_threads.push(thread::spawn(move || {
-                let my_map = Map::from_level(level);
-                let mut my_models = Vec::new();
-                my_models.push(best.0.clone());
-                //create 100 child model from one of the bests if the last generation
-                for _ in 0..500 {
-                    my_models.push(best.0.copy_mut(0.05, 0.01));
-                }
-                for _ in 0..500 {
-                    my_models.push(best.0.copy_mut(0.05, 0.5));
-                }
-                for _ in 0..500 {
-                    my_models.push(best.0.copy_mut(0.05, 0.5));
-                }
-
-                let bests_current = get_n_best_of_gen(&my_map, my_models, 2);
-                tx.send(bests_current).unwrap();
-            }));
-        }
-        bests = Vec::new();
-        //wait for for all thread
-        for thrd in my_threads {
-            thrd.join().unwrap();
-        }
-        //get bests of each thread
-        for rx in my_rx.iter() {
-            let best_rec = rx.recv().unwrap();
-            for a in best_rec {
-                bests.push(a);
+        let mut my_models = Vec::new();
+        for best in bests.iter() {
+            my_models.push(best.0.clone());
+            //create 1500 child model from one of the bests if the last generation
+            for _ in 0..500 {
+                my_models.push(best.0.copy_mut(0.05, 0.01));
+            }
+            for _ in 0..500 {
+                my_models.push(best.0.copy_mut(0.05, 0.5));
+            }
+            for _ in 0..500 {
+                my_models.push(best.0.copy_mut(0.05, 0.5));
             }
         }
-        bests.sort_by(|a, b| b.1.cmp(&a.1));
-        bests.truncate(8);
+
+        //keep 8 survivors spread over the species of this generation
+        let evaluated = pool.evaluate(my_models);
+        let mean = evaluated.iter().map(|e| e.1 as f64).sum::<f64>() / evaluated.len() as f64;
+        let best = evaluated.iter().map(|e| e.1).max().unwrap_or(0);
+        curve.push(evaluated.len(), mean, best as f64);
+        bests = species::select(generation, evaluated, SPECIES_THRESHOLD, 8);
     };
     let mut result = Vec::new();
     for m in bests {
@@ -288,6 +346,58 @@ fn train_with_genetic(map: &Map, model: Model) -> Vec<Model> {
     return result;
 }
 
+fn neat_distance(map: &Map, genome: &Genome) -> usize {
+    pool::run_episode(map, &genome.compile()).d as usize
+}
+
+fn train_with_neat(map: &Map) -> Genome {
+    let mut innovations = Innovations::new();
+    let mut genomes: Vec<Genome> = Vec::new();
+    let pool = Pool::new(Arc::new(map.clone()), neat_distance);
+
+    //initial population: inputs directly connected to the outputs
+    for _ in 0..300 {
+        genomes.push(Genome::new(58, 2, Activation::Tanh, &mut innovations));
+    }
+    let mut best = genomes[0].clone();
+    for generation in 0..50 {
+        let mut evaluated = pool.evaluate(genomes);
+        evaluated.sort_by(|a, b| b.1.cmp(&a.1));
+        println!("neat generation {}: best d={}, nodes={}, connections={}", generation,
+                 evaluated[0].1, evaluated[0].0.nodes.len(), evaluated[0].0.connections.len());
+        best = evaluated[0].0.clone();
+
+        //the 20% bests are the parents of the next generation
+        evaluated.truncate(60);
+        genomes = Vec::new();
+        for e in evaluated.iter().take(5) {
+            genomes.push(e.0.clone());
+        }
+        while genomes.len() < 300 {
+            let i = rand::random::<usize>() % evaluated.len();
+            let j = rand::random::<usize>() % evaluated.len();
+            //the fittest parent comes first, only compatible genomes are crossed
+            let (a, b) = (&evaluated[i.min(j)].0, &evaluated[i.max(j)].0);
+            let mut child = if a.distance(b, 1.0, 1.0, 0.4) < 3.0 {
+                a.crossover(b)
+            } else {
+                a.clone()
+            };
+            child.mutate_weights(0.1, 0.5);
+            let p: f64 = rand::random();
+            if p < 0.05 {
+                child.add_node(Activation::Tanh, &mut innovations);
+            }
+            let p: f64 = rand::random();
+            if p < 0.1 {
+                child.add_connection(&mut innovations);
+            }
+            genomes.push(child);
+        }
+    }
+    return best;
+}
+
 fn main() {
     let mut window = RenderWindow::new(
         (1024, 1024),
@@ -298,25 +408,256 @@ fn main() {
     window.set_framerate_limit(30);
     window.set_vertical_sync_enabled(true);
 
+    //x * sigmoid(x), saved models can use it as custom(swish)
+    register_activation("swish", |x: f64| x / (1. + (-x).exp()), None::<fn(f64) -> f64>);
+
     let map = Map::from_level(Level::Level1);
+    let mut renderer = Renderer::new(&map);
+    let mut playback = Playback::new();
     let mut model = Model::new();
     let l1 = Layer::new_random(58, 20, Activation::Sigmoid);
     // let l2 = Layer::new_random(20, 20, Activation::Sigmoid);
     let l3 = Layer::new_random(20, 10, Activation::Sigmoid);
     // let l4 = Layer::new_random(10, 10, Activation::Sigmoid);
     let l5 = Layer::new_random(10, 2, Activation::Tanh);    
-    model.add_layer(l1);
+    model.add_layer(l1).unwrap();
     // model.add_layer(l2);
-    model.add_layer(l3);
+    model.add_layer(l3).unwrap();
     // model.add_layer(l4);
-    model.add_layer(l5);
+    model.add_layer(l5).unwrap();
+    print!("{}", model.summary());
 
-    let best = train_with_genetic(&map, model);
-
-    let unic_best = get_best_of_gen(&map, best);
-    
-    loop {
-        show_model(&mut window, &map, &unic_best);
+    let args: Vec<String> = std::env::args().collect();
+    let mode = args.get(1).map(|s| s.as_str());
+    let best = match mode {
+        Some("drive") => {
+            drive(&mut window, &mut renderer, &map, args.get(2).map(|s| s.as_str()).unwrap_or(DEMONSTRATIONS));
+            return;
+        },
+        Some("show") => {
+            let path = args.get(2).map(|s| s.as_str()).unwrap_or(BEST_MODEL);
+            //a model written by the quantize mode
+            if let Ok(quantized) = quant::load(path) {
+                println!("int8 model of {} layers, {} bytes", quantized.layers.len(), quantized.size_in_bytes());
+                while !show_model(&mut window, &mut renderer, &mut playback, &map, &quantized) {}
+                return;
+            }
+            //a q network written by the dqn mode, driven through its discrete actions
+            if let Ok(policy) = dqn::load(path) {
+                print!("{}", policy.q.summary());
+                while !show_model(&mut window, &mut renderer, &mut playback, &map, &policy) {}
+                return;
+            }
+            let model = save::load(path).expect("can not load the model");
+            print!("{}", model.summary());
+            model.check_input(OBSERVATION_SIZE).or_else(|_| model.check_input(RADAR_SIZE))
+                .expect("this model can not drive");
+            if args.get(3).map(|s| s.as_str()) == Some("f32") {
+                //same weights, evaluated in single precision
+                let single = model.cast::<f32>();
+                println!("f32: d={}, f64: d={}", pool::run_episode(&map, &single).d,
+                         pool::distance(&map, &model));
+                while !show_model(&mut window, &mut renderer, &mut playback, &map, &single) {}
+                return;
+            }
+            model
+        },
+        Some("clone") => {
+            //the teacher is a saved model or recorded demonstrations
+            let path = args.get(2).map(|s| s.as_str()).unwrap_or(BEST_MODEL);
+            let teacher = save::load(path).ok();
+            let dataset = match teacher {
+                Some(ref t) => cloning::collect(&map, t, 20, 0.1),
+                None => Dataset::load(path).expect("can not load the teacher"),
+            };
+            println!("{} samples to learn from", dataset.len());
+            let hidden = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(8);
+            //the student reads what the teacher read: observation or radar grid
+            let inputs = match teacher {
+                Some(ref t) => t.input_size().unwrap_or(OBSERVATION_SIZE),
+                None => dataset.samples.first().map(|s| s.0.len() as u32).unwrap_or(OBSERVATION_SIZE),
+            };
+            let mut student = Model::new();
+            student.add_layer(Layer::new_init(inputs, hidden, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            student.add_layer(Layer::new_init(hidden, 2, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            let mut optimizer = match args.get(4).map(|s| s.as_str()) {
+                Some("sgd") => Optimizer::sgd(0.05),
+                Some("momentum") => Optimizer::momentum(0.01),
+                _ => Optimizer::adam(0.001),
+            };
+            println!("optimizer: {:?}", optimizer.method);
+            cloning::train(&map, student, &dataset, teacher.as_ref(), 200, &mut optimizer)
+        },
+        Some("dqn") => {
+            let mut q = Model::new();
+            q.add_layer(Layer::new_init(58, 32, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            q.add_layer(Layer::new_init(32, dqn::ACTIONS as u32, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            let mut dqn = Dqn::new();
+            dqn.epsilon = match args.get(2).map(|s| s.as_str()) {
+                Some("constant") => dqn::Schedule::Constant(args.get(3).and_then(|s| s.parse().ok()).unwrap_or(0.1)),
+                Some("exponential") => dqn::Schedule::Exponential(1.0, 0.05, 0.9999),
+                _ => dqn.epsilon,
+            };
+            println!("epsilon: {:?}", dqn.epsilon);
+            let mut curve = Curve::new();
+            let best = dqn.train(&map, q, 2000, &mut curve);
+            curve.save("dqn.csv").expect("can not save the learning curve");
+            dqn::save(&best, "dqn.model").expect("can not save the model");
+            println!("q network saved in dqn.model");
+            while !show_model(&mut window, &mut renderer, &mut playback, &map, &best) {}
+            return;
+        },
+        Some("quantize") => {
+            //int8 export of a saved model, checked on the chosen level
+            let path = args.get(2).map(|s| s.as_str()).unwrap_or(BEST_MODEL);
+            let model = save::load(path).expect("can not load the model");
+            let map = match args.get(3).map(|s| s.as_str()) {
+                Some("2") => Map::from_level(Level::Level2),
+                Some("3") => Map::from_level(Level::Level3),
+                _ => map.clone(),
+            };
+            let mut renderer = Renderer::new(&map);
+            let dataset = cloning::collect(&map, &model, 5, 0.05);
+            let indices: Vec<usize> = (0..dataset.len()).collect();
+            let (inputs, _) = dataset.batch(&indices);
+            let quantized = quant::quantize(&model, &inputs).expect("can not quantize this model");
+            let r = quant::report(&map, &model, &quantized, &inputs);
+            println!("{} observations: max error={:.5}, mean error={:.5}", inputs.ncols(), r.max_error, r.mean_error);
+            println!("float: d={}, {} bytes; int8: d={}, {} bytes", r.float_d, r.float_bytes, r.quantized_d, r.quantized_bytes);
+            quant::save(&quantized, QUANTIZED_MODEL).expect("can not save the quantized model");
+            println!("quantized model saved in {}", QUANTIZED_MODEL);
+            while !show_model(&mut window, &mut renderer, &mut playback, &map, &quantized) {}
+            return;
+        },
+        Some("export") => {
+            //standalone source of a saved model, checked against get_result
+            let path = args.get(2).map(|s| s.as_str()).unwrap_or(BEST_MODEL);
+            let model = save::load(path).expect("can not load the model");
+            let language = match args.get(3).map(|s| s.as_str()) {
+                Some("c") => Language::C,
+                _ => Language::Rust,
+            };
+            let dataset = cloning::collect(&map, &model, 2, 0.1);
+            let indices: Vec<usize> = (0..dataset.len()).collect();
+            let (samples, _) = dataset.batch(&indices);
+            codegen::export(&model, language, EXPORT_DIR).expect("can not export the model");
+            println!("{:?} code written in {}", language, EXPORT_DIR);
+            match codegen::verify(&model, language, &samples, EXPORT_DIR, EXPORT_TOLERANCE) {
+                Ok(error) => println!("{} observations: largest difference with the model={:e}", samples.ncols(), error),
+                Err(e) => println!("the generated code is wrong: {}", e),
+            }
+            return;
+        },
+        Some("prune") => {
+            let path = args.get(2).map(|s| s.as_str()).unwrap_or(BEST_MODEL);
+            let model = save::load(path).expect("can not load the model");
+            let pruned = match args.get(3).map(|s| s.as_str()) {
+                Some("fitness") => {
+                    //the amount is the part of the distance we accept to lose
+                    let tolerance = args.get(4).and_then(|s| s.parse().ok()).unwrap_or(0.05);
+                    pruning::fitness_aware(&map, &model, tolerance, 64)
+                },
+                _ => {
+                    //the amount is the part of the weights to prune
+                    let fraction = args.get(4).and_then(|s| s.parse().ok()).unwrap_or(0.5);
+                    pruning::magnitude(&model, fraction)
+                },
+            };
+            let r = pruning::report(&map, &model, &pruned);
+            println!("pruned: weight sparsity {:.1}% -> {:.1}%, d {} -> {}", r.sparsity_before * 100.,
+                     r.sparsity_after * 100., r.fitness_before, r.fitness_after);
+            let generations = args.get(5).and_then(|s| s.parse().ok()).unwrap_or(0);
+            if generations > 0 {
+                let evolved = pruning::reevolve(&map, pruned, generations);
+                let r = pruning::report(&map, &model, &evolved);
+                println!("re-evolved: weight sparsity {:.1}% -> {:.1}%, d {} -> {}", r.sparsity_before * 100.,
+                         r.sparsity_after * 100., r.fitness_before, r.fitness_after);
+                evolved
+            } else {
+                pruned
+            }
+        },
+        Some("neat") => {
+            let best = train_with_neat(&map).compile();
+            while !show_model(&mut window, &mut renderer, &mut playback, &map, &best) {}
+            return;
+        },
+        Some("novelty") => {
+            let kind = match args.get(2).map(|s| s.as_str()) {
+                Some("final") => Characterization::FinalPosition,
+                Some("trace") => Characterization::Trace(10),
+                _ => Characterization::Cells(32.0),
+            };
+            let blend = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(0.0);
+            let best = novelty::train(&map, model, kind, blend);
+            get_best_of_gen(&map, best)
+        },
+        Some("es") => {
+            OpenAiEs::new().train(&map, model.copy_mut(1.0, 1.0), 100)
+        },
+        Some("cmaes") => {
+            //cma-es needs a small network
+            let mut small = Model::new();
+            small.add_layer(Layer::new_random(58, 6, Activation::Tanh)).unwrap();
+            small.add_layer(Layer::new_random(6, 2, Activation::Tanh)).unwrap();
+            let cma = CmaEs::new(0.5, small.num_parameters());
+            cma.train(&map, small, 200)
+        },
+        Some("reinforce") | Some("a2c") => {
+            let method = if mode == Some("a2c") { rl::Method::ActorCritic } else { rl::Method::Reinforce };
+            let mut curve = Curve::new();
+            let best = PolicyGradient::new(method).train(&map, model.copy_mut(1.0, 1.0), 300, &mut curve);
+            let path = format!("{}.csv", mode.unwrap());
+            curve.save(&path).expect("can not save the learning curve");
+            println!("learning curve saved in {}", path);
+            best
+        },
+        Some("elman") | Some("gru") => {
+            //genetic training of a controller with memory
+            let kind = Kind::from_name(mode.unwrap()).unwrap();
+            let mut recurrent = Model::new();
+            recurrent.add_layer(Layer::new_kind(kind, 58, 12, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            recurrent.add_layer(Layer::new_init(12, 2, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            print!("{}", recurrent.summary());
+            let mut curve = Curve::new();
+            let best = train_with_genetic(&map, recurrent, &mut curve);
+            let path = format!("{}.csv", mode.unwrap());
+            curve.save(&path).expect("can not save the learning curve");
+            println!("learning curve saved in {}", path);
+            get_best_of_gen(&map, best)
+        },
+        Some("conv") => {
+            //genetic training of a network reading the radar as an image
+            let conv = Conv::radar(4, 3, 1, 2);
+            let mut cnn = Model::new();
+            cnn.add_layer(Layer::new_conv(conv, Activation::Relu, Init::He, rand::random())).unwrap();
+            cnn.add_layer(Layer::new_init(conv.output_size(), 8, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            cnn.add_layer(Layer::new_init(8, 2, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            print!("{}", cnn.summary());
+            let mut curve = Curve::new();
+            let best = train_with_genetic(&map, cnn, &mut curve);
+            curve.save("conv.csv").expect("can not save the learning curve");
+            println!("learning curve saved in conv.csv");
+            get_best_of_gen(&map, best)
+        },
+        _ => {
+            let mut curve = Curve::new();
+            let best = train_with_genetic(&map, model, &mut curve);
+            curve.save("genetic.csv").expect("can not save the learning curve");
+            println!("learning curve saved in genetic.csv");
+            get_best_of_gen(&map, best)
+        },
+    };
+    if mode != Some("show") {
+        //do not overwrite the teacher
+        let path = match mode {
+            Some("clone") => STUDENT_MODEL,
+            Some("prune") => PRUNED_MODEL,
+            _ => BEST_MODEL,
+        };
+        save::save(&best, path).expect("can not save the model");
+        println!("best model saved in {}", path);
     }
-}
 
+    while !show_model(&mut window, &mut renderer, &mut playback, &map, &best) {}
+}
@@ -0,0 +1,482 @@
+pub mod codegen {
+    use na::DMatrix;
+    use crate::model::model::{Activation, Kind, Model, ModelError};
+    use std::fs;
+    use std::io::{self, Error, ErrorKind, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Language {
+        // a module that only uses core, for no_std crates
+        Rust,
+        // model.h and model.c, only needs math.h
+        C,
+    }
+
+    // Only dense layers with built-in activations can be exported.
+    fn check(model: &Model) -> Result<(), ModelError> {
+        if model.layers().is_empty() {
+            return Err(ModelError::Empty);
+        }
+        for (i, a) in model.layers().iter().enumerate() {
+            if a.kind() != Kind::Dense {
+                return Err(ModelError::NotDense { layer: i, kind: a.kind().name() });
+            }
+            if let Activation::Custom(ref name) = *a.activation() {
+                return Err(ModelError::CustomActivation { layer: i, name: name.clone() });
+            }
+        }
+        return Ok(());
+    }
+
+    // shortest text that reads back as the same f64, valid in Rust and in C
+    fn number(x: f64) -> String {
+        format!("{:?}", x)
+    }
+
+    // expression of the activation of one neuron, except softmax which
+    // works on the whole layer
+    fn activation(a: &Activation, x: &str) -> String {
+        match *a {
+            Activation::Relu => format!("relu({})", x),
+            Activation::Sigmoid => format!("sigmoid({})", x),
+            Activation::Tanh => format!("tanh({})", x),
+            Activation::ArcTan => format!("atan({})", x),
+            Activation::Step => format!("step({})", x),
+            Activation::Logistic(l, k, x0) => format!("logistic({}, {}, {}, {})", x, number(l), number(k), number(x0)),
+            Activation::Softsign => format!("softsign({})", x),
+            Activation::Linear | Activation::Softmax | Activation::Custom(_) => x.to_string(),
+            Activation::LeakyRelu(alpha) => format!("leaky_relu({}, {})", x, number(alpha)),
+            Activation::Elu(alpha) => format!("elu({}, {})", x, number(alpha)),
+        }
+    }
+
+    fn values<'a, I: Iterator<Item = &'a f64>>(values: I) -> String {
+        values.map(|v| number(*v)).collect::<Vec<String>>().join(", ")
+    }
+
+    const RUST_HELPERS: &str = "
+// core has no float functions, these are accurate to about 1e-15
+fn abs(x: f64) -> f64 {
+    if x < 0.0 { -x } else { x }
+}
+
+fn exp(x: f64) -> f64 {
+    if x > 709.0 {
+        return f64::INFINITY;
+    }
+    if x < -745.0 {
+        return 0.0;
+    }
+    // x = k ln 2 + r with |r| <= ln 2 / 2
+    let t = x / core::f64::consts::LN_2;
+    let k = if t < 0.0 { (t - 0.5) as i64 } else { (t + 0.5) as i64 };
+    let r = x - k as f64 * core::f64::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..20 {
+        term = term * r / n as f64;
+        sum += term;
+    }
+    // 2^k in two steps so that it stays a normal number
+    let half = k / 2;
+    let two = |e: i64| f64::from_bits(((e + 1023) as u64) << 52);
+    sum * two(half) * two(k - half)
+}
+
+fn tanh(x: f64) -> f64 {
+    if x > 20.0 {
+        return 1.0;
+    }
+    if x < -20.0 {
+        return -1.0;
+    }
+    let e = exp(2.0 * x);
+    (e - 1.0) / (e + 1.0)
+}
+
+fn atan(x: f64) -> f64 {
+    if x < 0.0 {
+        return -atan(-x);
+    }
+    if x > 1.0 {
+        return core::f64::consts::FRAC_PI_2 - atan(1.0 / x);
+    }
+    // atan(x) = pi / 6 + atan((x sqrt(3) - 1) / (x + sqrt(3)))
+    let sqrt3 = 1.7320508075688772;
+    if x > 0.2679491924311227 {
+        return core::f64::consts::FRAC_PI_6 + atan((x * sqrt3 - 1.0) / (x + sqrt3));
+    }
+    let mut sum = 0.0;
+    let mut power = x;
+    for n in 0..16 {
+        let term = power / (2 * n + 1) as f64;
+        sum += if n % 2 == 0 { term } else { -term };
+        power = power * x * x;
+    }
+    sum
+}
+
+fn relu(x: f64) -> f64 {
+    if x < 0.0 { 0.0 } else { x }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + exp(-x))
+}
+
+fn step(x: f64) -> f64 {
+    if x < 0.0 { 0.0 } else { 1.0 }
+}
+
+fn logistic(x: f64, l: f64, k: f64, x0: f64) -> f64 {
+    l / (1.0 + exp(-k * (x - x0)))
+}
+
+fn softsign(x: f64) -> f64 {
+    x / (1.0 + abs(x))
+}
+
+fn leaky_relu(x: f64, alpha: f64) -> f64 {
+    if x < 0.0 { alpha * x } else { x }
+}
+
+fn elu(x: f64, alpha: f64) -> f64 {
+    if x < 0.0 { alpha * (exp(x) - 1.0) } else { x }
+}
+
+fn softmax(x: &mut [f64]) {
+    let mut max = x[0];
+    for v in x.iter() {
+        if *v > max {
+            max = *v;
+        }
+    }
+    let mut sum = 0.0;
+    for v in x.iter_mut() {
+        *v = exp(*v - max);
+        sum += *v;
+    }
+    for v in x.iter_mut() {
+        *v = *v / sum;
+    }
+}
+";
+
+    const C_HELPERS: &str = "
+static double relu(double x) {
+    return x < 0.0 ? 0.0 : x;
+}
+
+static double sigmoid(double x) {
+    return 1.0 / (1.0 + exp(-x));
+}
+
+static double step(double x) {
+    return x < 0.0 ? 0.0 : 1.0;
+}
+
+static double logistic(double x, double l, double k, double x0) {
+    return l / (1.0 + exp(-k * (x - x0)));
+}
+
+static double softsign(double x) {
+    return x / (1.0 + fabs(x));
+}
+
+static double leaky_relu(double x, double alpha) {
+    return x < 0.0 ? alpha * x : x;
+}
+
+static double elu(double x, double alpha) {
+    return x < 0.0 ? alpha * (exp(x) - 1.0) : x;
+}
+
+static void softmax(double *x, int n) {
+    double max = x[0];
+    double sum = 0.0;
+    int i;
+    for (i = 0; i < n; i++) {
+        if (x[i] > max) {
+            max = x[i];
+        }
+    }
+    for (i = 0; i < n; i++) {
+        x[i] = exp(x[i] - max);
+        sum += x[i];
+    }
+    for (i = 0; i < n; i++) {
+        x[i] = x[i] / sum;
+    }
+}
+";
+
+    // A module with the weights as constant arrays and
+    // `pub fn predict(inputs: &[f64; INPUTS]) -> [f64; OUTPUTS]`.
+    pub fn rust(model: &Model) -> Result<String, ModelError> {
+        check(model)?;
+        let layers = model.layers();
+        let inputs = layers[0].input();
+        let outputs = layers[layers.len() - 1].neuron();
+        let mut s = String::new();
+        s.push_str("// Generated from a trained model, do not edit.\n");
+        s.push_str("// Only uses core, so it can be included in a no_std crate.\n");
+        s.push_str("#![allow(dead_code, clippy::all)]\n\n");
+        s.push_str(&format!("pub const INPUTS: usize = {};\npub const OUTPUTS: usize = {};\n\n", inputs, outputs));
+        for (i, a) in layers.iter().enumerate() {
+            let params = a.params();
+            let (w, b) = (params[0].1, params[1].1);
+            s.push_str(&format!("// {} -> {}, {}\n", a.input(), a.neuron(), a.activation().name()));
+            s.push_str(&format!("const W{}: [[f64; {}]; {}] = [\n", i, a.input(), a.neuron()));
+            for row in w.row_iter() {
+                s.push_str(&format!("    [{}],\n", values(row.iter())));
+            }
+            s.push_str("];\n");
+            s.push_str(&format!("const B{}: [f64; {}] = [{}];\n\n", i, a.neuron(), values(b.iter())));
+        }
+        s.push_str(&format!("pub fn predict(inputs: &[f64; {}]) -> [f64; {}] {{\n", inputs, outputs));
+        for (i, a) in layers.iter().enumerate() {
+            let previous = if i == 0 { "inputs".to_string() } else { format!("x{}", i - 1) };
+            s.push_str(&format!("    let mut x{} = [0.0f64; {}];\n", i, a.neuron()));
+            s.push_str(&format!("    for i in 0..{} {{\n", a.neuron()));
+            s.push_str(&format!("        let mut sum = B{}[i];\n", i));
+            s.push_str(&format!("        for j in 0..{} {{\n", a.input()));
+            s.push_str(&format!("            sum += W{}[i][j] * {}[j];\n", i, previous));
+            s.push_str("        }\n");
+            s.push_str(&format!("        x{}[i] = {};\n", i, activation(a.activation(), "sum")));
+            s.push_str("    }\n");
+            if let Activation::Softmax = *a.activation() {
+                s.push_str(&format!("    softmax(&mut x{});\n", i));
+            }
+        }
+        s.push_str(&format!("    x{}\n}}\n", layers.len() - 1));
+        s.push_str(RUST_HELPERS);
+        return Ok(s);
+    }
+
+    // model.h and model.c, with
+    // `void predict(const double inputs[MODEL_INPUTS], double outputs[MODEL_OUTPUTS])`.
+    pub fn c(model: &Model) -> Result<(String, String), ModelError> {
+        check(model)?;
+        let layers = model.layers();
+        let inputs = layers[0].input();
+        let outputs = layers[layers.len() - 1].neuron();
+        let mut h = String::new();
+        h.push_str("/* Generated from a trained model, do not edit. */\n");
+        h.push_str("#ifndef MODEL_H\n#define MODEL_H\n\n");
+        h.push_str(&format!("#define MODEL_INPUTS {}\n#define MODEL_OUTPUTS {}\n\n", inputs, outputs));
+        h.push_str("void predict(const double inputs[MODEL_INPUTS], double outputs[MODEL_OUTPUTS]);\n\n");
+        h.push_str("#endif\n");
+
+        let mut s = String::new();
+        s.push_str("/* Generated from a trained model, do not edit. */\n");
+        s.push_str("#include <math.h>\n#include \"model.h\"\n\n");
+        for (i, a) in layers.iter().enumerate() {
+            let params = a.params();
+            let (w, b) = (params[0].1, params[1].1);
+            s.push_str(&format!("/* {} -> {}, {} */\n", a.input(), a.neuron(), a.activation().name()));
+            s.push_str(&format!("static const double W{}[{}][{}] = {{\n", i, a.neuron(), a.input()));
+            for row in w.row_iter() {
+                s.push_str(&format!("    {{{}}},\n", values(row.iter())));
+            }
+            s.push_str("};\n");
+            s.push_str(&format!("static const double B{}[{}] = {{{}}};\n\n", i, a.neuron(), values(b.iter())));
+        }
+        s.push_str(C_HELPERS);
+        s.push_str("\nvoid predict(const double inputs[MODEL_INPUTS], double outputs[MODEL_OUTPUTS]) {\n");
+        for (i, a) in layers.iter().enumerate() {
+            s.push_str(&format!("    double x{}[{}];\n", i, a.neuron()));
+        }
+        s.push_str("    double sum;\n    int i, j;\n");
+        for (i, a) in layers.iter().enumerate() {
+            let previous = if i == 0 { "inputs".to_string() } else { format!("x{}", i - 1) };
+            s.push_str(&format!("    for (i = 0; i < {}; i++) {{\n", a.neuron()));
+            s.push_str(&format!("        sum = B{}[i];\n", i));
+            s.push_str(&format!("        for (j = 0; j < {}; j++) {{\n", a.input()));
+            s.push_str(&format!("            sum += W{}[i][j] * {}[j];\n", i, previous));
+            s.push_str("        }\n");
+            s.push_str(&format!("        x{}[i] = {};\n", i, activation(a.activation(), "sum")));
+            s.push_str("    }\n");
+            if let Activation::Softmax = *a.activation() {
+                s.push_str(&format!("    softmax(x{}, {});\n", i, a.neuron()));
+            }
+        }
+        s.push_str(&format!("    for (i = 0; i < {}; i++) {{\n        outputs[i] = x{}[i];\n    }}\n}}\n",
+                            outputs, layers.len() - 1));
+        return Ok((h, s));
+    }
+
+    // Write the generated files in `dir`: model.rs, or model.h and model.c.
+    pub fn export<P: AsRef<Path>>(model: &Model, language: Language, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let invalid = |e: ModelError| Error::new(ErrorKind::InvalidInput, e.to_string());
+        match language {
+            Language::Rust => fs::write(dir.join("model.rs"), rust(model).map_err(invalid)?),
+            Language::C => {
+                let (h, c) = c(model).map_err(invalid)?;
+                fs::write(dir.join("model.h"), h)?;
+                fs::write(dir.join("model.c"), c)
+            },
+        }
+    }
+
+    const RUST_DRIVER: &str = "
+mod model;
+use std::io::BufRead;
+
+fn main() {
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let mut inputs = [0.0f64; model::INPUTS];
+        for (i, v) in line.split_whitespace().enumerate() {
+            inputs[i] = v.parse().unwrap();
+        }
+        let outputs: Vec<String> = model::predict(&inputs).iter().map(|v| format!(\"{:?}\", v)).collect();
+        println!(\"{}\", outputs.join(\" \"));
+    }
+}
+";
+
+    const C_DRIVER: &str = "
+#include <stdio.h>
+#include \"model.h\"
+
+int main(void) {
+    double inputs[MODEL_INPUTS];
+    double outputs[MODEL_OUTPUTS];
+    int i;
+    for (;;) {
+        for (i = 0; i < MODEL_INPUTS; i++) {
+            if (scanf(\"%lf\", &inputs[i]) != 1) {
+                return 0;
+            }
+        }
+        predict(inputs, outputs);
+        for (i = 0; i < MODEL_OUTPUTS; i++) {
+            printf(\"%.17g \", outputs[i]);
+        }
+        printf(\"\\n\");
+    }
+}
+";
+
+    fn run(command: &mut Command) -> io::Result<()> {
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(Error::new(ErrorKind::Other, String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+        return Ok(());
+    }
+
+    // Test harness: export the model in `dir` with a small program reading
+    // observations on stdin, compile it with rustc or cc, run it on
+    // `samples` (one observation per column) and return the largest
+    // difference with Model::get_result. A difference above `tolerance`
+    // is an error.
+    pub fn verify<P: AsRef<Path>>(model: &Model, language: Language, samples: &DMatrix<f64>, dir: P,
+                                  tolerance: f64) -> io::Result<f64> {
+        let dir = dir.as_ref();
+        export(model, language, dir)?;
+        let binary = dir.join("predict");
+        match language {
+            Language::Rust => {
+                fs::write(dir.join("main.rs"), RUST_DRIVER)?;
+                run(Command::new("rustc").arg("-O").arg("--edition=2018").arg("-o").arg(&binary).arg(dir.join("main.rs")))?;
+            },
+            Language::C => {
+                fs::write(dir.join("main.c"), C_DRIVER)?;
+                run(Command::new("cc").arg("-O2").arg("-o").arg(&binary)
+                    .arg(dir.join("main.c")).arg(dir.join("model.c")).arg("-lm"))?;
+            },
+        }
+
+        let mut input = String::new();
+        for col in samples.column_iter() {
+            let line: Vec<String> = col.iter().map(|v| format!("{:?}", v)).collect();
+            input.push_str(&line.join(" "));
+            input.push('\n');
+        }
+        let mut child = Command::new(&binary).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        // written from another thread, the binary blocks once the pipe of
+        // its output is full and we only read it in wait_with_output
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        writer.join().map_err(|_| Error::new(ErrorKind::Other, "the writer thread panicked"))??;
+        let text = String::from_utf8_lossy(&output.stdout);
+
+        let expected = model.get_result(samples);
+        let mut max: f64 = 0.;
+        let mut lines = 0;
+        for (j, line) in text.lines().enumerate() {
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if j >= samples.ncols() || values.len() != expected.nrows() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("bad output {}: {}", j, line)));
+            }
+            for (i, v) in values.iter().enumerate() {
+                let v: f64 = v.parse().map_err(|_| Error::new(ErrorKind::InvalidData, format!("bad output: {}", line)))?;
+                let d = (v - expected[(i, j)]).abs();
+                // also catches a NaN
+                if !(d <= tolerance) {
+                    return Err(Error::new(ErrorKind::InvalidData,
+                                          format!("the generated code differs from the model by {:e} on sample {}", d, j)));
+                }
+                max = max.max(d);
+            }
+            lines += 1;
+        }
+        if lines != samples.ncols() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} outputs for {} samples", lines, samples.ncols())));
+        }
+        return Ok(max);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::{Init, Layer};
+        use rand::prelude::*;
+        use rand::rngs::StdRng;
+
+        // every activation the generated code implements by hand
+        fn model() -> Model {
+            let mut model = Model::new();
+            model.add_layer(Layer::new_init(6, 5, Activation::Tanh, Init::XavierUniform, 1)).unwrap();
+            model.add_layer(Layer::new_init(5, 5, Activation::Sigmoid, Init::XavierUniform, 2)).unwrap();
+            model.add_layer(Layer::new_init(5, 5, Activation::ArcTan, Init::XavierUniform, 3)).unwrap();
+            model.add_layer(Layer::new_init(5, 4, Activation::Elu(0.5), Init::XavierUniform, 4)).unwrap();
+            model.add_layer(Layer::new_init(4, 4, Activation::Logistic(2., 1.5, 0.5), Init::XavierUniform, 5)).unwrap();
+            model.add_layer(Layer::new_init(4, 3, Activation::Softmax, Init::XavierUniform, 6)).unwrap();
+            return model;
+        }
+
+        fn check_language(language: Language, name: &str, n: usize) {
+            let mut rng = StdRng::seed_from_u64(3);
+            let samples = DMatrix::from_fn(6, n, |_, _| rng.gen_range(-20., 20.));
+            let dir = std::env::temp_dir().join(format!("codegen_test_{}_{}", name, std::process::id()));
+            let result = verify(&model(), language, &samples, &dir, 1e-9);
+            let _ = fs::remove_dir_all(&dir);
+            assert!(result.is_ok(), "{:?}", result);
+        }
+
+        #[test]
+        fn rust_matches_the_model() {
+            check_language(Language::Rust, "rust", 200);
+        }
+
+        #[test]
+        fn c_matches_the_model() {
+            check_language(Language::C, "c", 200);
+        }
+
+        // more output than a pipe holds while the input is still written
+        #[test]
+        fn many_samples() {
+            check_language(Language::C, "many", 10000);
+        }
+    }
+}
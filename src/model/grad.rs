@@ -0,0 +1,307 @@
+pub mod grad {
+    use na::{DMatrix, DVector};
+    use rand::prelude::*;
+    use crate::model::model::Model;
+    use std::fs;
+    use std::io::{self, Error, ErrorKind};
+    use std::path::Path;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Loss {
+        Mse,
+    }
+
+    impl Loss {
+        // mean over the batch, one sample per column
+        pub fn value(&self, output: &DMatrix<f64>, target: &DMatrix<f64>) -> f64 {
+            let n = output.ncols().max(1) as f64;
+            match *self {
+                Loss::Mse => (output - target).map(|d| d * d).sum() / n,
+            }
+        }
+
+        pub fn gradient(&self, output: &DMatrix<f64>, target: &DMatrix<f64>) -> DMatrix<f64> {
+            let n = output.ncols().max(1) as f64;
+            match *self {
+                Loss::Mse => (output - target) * (2. / n),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Method {
+        Sgd(f64),
+        // learning rate, momentum
+        Momentum(f64, f64),
+        // learning rate, beta1, beta2
+        Adam(f64, f64, f64),
+    }
+
+    pub struct Optimizer {
+        pub method: Method,
+        velocity: DVector<f64>,
+        m: DVector<f64>,
+        v: DVector<f64>,
+        t: i32,
+    }
+
+    impl Optimizer {
+        pub fn new(method: Method) -> Optimizer {
+            Optimizer {
+                method: method,
+                velocity: DVector::zeros(0),
+                m: DVector::zeros(0),
+                v: DVector::zeros(0),
+                t: 0,
+            }
+        }
+
+        pub fn sgd(learning_rate: f64) -> Optimizer {
+            Optimizer::new(Method::Sgd(learning_rate))
+        }
+
+        pub fn momentum(learning_rate: f64) -> Optimizer {
+            Optimizer::new(Method::Momentum(learning_rate, 0.9))
+        }
+
+        pub fn adam(learning_rate: f64) -> Optimizer {
+            Optimizer::new(Method::Adam(learning_rate, 0.9, 0.999))
+        }
+
+        pub fn step(&mut self, parameters: &mut DVector<f64>, gradient: &DVector<f64>) {
+            if self.velocity.len() != parameters.len() {
+                self.velocity = DVector::zeros(parameters.len());
+                self.m = DVector::zeros(parameters.len());
+                self.v = DVector::zeros(parameters.len());
+                self.t = 0;
+            }
+            match self.method {
+                Method::Sgd(lr) => {
+                    *parameters -= gradient * lr;
+                },
+                Method::Momentum(lr, momentum) => {
+                    self.velocity = &self.velocity * momentum - gradient * lr;
+                    *parameters += &self.velocity;
+                },
+                Method::Adam(lr, beta1, beta2) => {
+                    self.t += 1;
+                    self.m = &self.m * beta1 + gradient * (1. - beta1);
+                    self.v = &self.v * beta2 + gradient.map(|g| g * g) * (1. - beta2);
+                    let c1 = 1. - beta1.powi(self.t);
+                    let c2 = 1. - beta2.powi(self.t);
+                    for i in 0..parameters.len() {
+                        let m = self.m[i] / c1;
+                        let v = self.v[i] / c2;
+                        parameters[i] -= lr * m / (v.sqrt() + 1e-8);
+                    }
+                },
+            }
+        }
+    }
+
+    // Pairs of observation and expected output of the model.
+    pub struct Dataset {
+        pub samples: Vec<(DVector<f64>, DVector<f64>)>,
+    }
+
+    impl Dataset {
+        pub fn new() -> Dataset {
+            Dataset {
+                samples: Vec::new(),
+            }
+        }
+
+        pub fn push(&mut self, input: DVector<f64>, target: DVector<f64>) {
+            self.samples.push((input, target));
+        }
+
+        pub fn len(&self) -> usize {
+            self.samples.len()
+        }
+
+        // One sample per line: the input values, `|`, then the target values.
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let mut s = String::new();
+            for (input, target) in self.samples.iter() {
+                let input: Vec<String> = input.iter().map(|v| v.to_string()).collect();
+                let target: Vec<String> = target.iter().map(|v| v.to_string()).collect();
+                s.push_str(&format!("{} | {}\n", input.join(" "), target.join(" ")));
+            }
+            fs::write(path, s)
+        }
+
+        pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Dataset> {
+            let mut dataset = Dataset::new();
+            for line in fs::read_to_string(path)?.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let parts: Vec<&str> = line.split('|').collect();
+                if parts.len() != 2 {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("bad sample: {}", line)));
+                }
+                let mut values = Vec::new();
+                for part in parts {
+                    let mut v = Vec::new();
+                    for x in part.split_whitespace() {
+                        v.push(x.parse::<f64>().map_err(|_| Error::new(ErrorKind::InvalidData, format!("bad value: {}", x)))?);
+                    }
+                    values.push(DVector::from_vec(v));
+                }
+                let target = values.pop().unwrap();
+                let input = values.pop().unwrap();
+                dataset.push(input, target);
+            }
+            return Ok(dataset);
+        }
+
+        // inputs and targets of the samples, one per column
+        pub fn batch(&self, indices: &[usize]) -> (DMatrix<f64>, DMatrix<f64>) {
+            let inputs: Vec<DVector<f64>> = indices.iter().map(|i| self.samples[*i].0.clone()).collect();
+            let targets: Vec<DVector<f64>> = indices.iter().map(|i| self.samples[*i].1.clone()).collect();
+            (DMatrix::from_columns(&inputs), DMatrix::from_columns(&targets))
+        }
+    }
+
+    impl Model {
+        // Mean loss of the model over the whole dataset.
+        pub fn loss(&self, dataset: &Dataset, loss: Loss) -> f64 {
+            if dataset.len() == 0 {
+                return 0.;
+            }
+            let indices: Vec<usize> = (0..dataset.len()).collect();
+            let (input, target) = dataset.batch(&indices);
+            let trace = self.forward_trace(&input);
+            match trace.last() {
+                Some(t) => loss.value(&t.1, &target),
+                None => loss.value(&input, &target),
+            }
+        }
+
+        // One optimizer step on a batch, returns the loss before the step.
+        pub fn train_batch(&mut self, input: &DMatrix<f64>, target: &DMatrix<f64>, loss: Loss, optimizer: &mut Optimizer) -> f64 {
+            let trace = self.forward_trace(input);
+            let output = &trace.last().expect("the model has no layer").1;
+            let value = loss.value(output, target);
+            let gradient = self.backward(input, &trace, loss.gradient(output, target));
+            let mut parameters = self.parameters();
+            optimizer.step(&mut parameters, &gradient);
+            self.set_parameters(&parameters);
+            return value;
+        }
+
+        // Mean squared error with Adam, returns the mean loss of every epoch.
+        pub fn fit(&mut self, dataset: &Dataset, epochs: usize, batch_size: usize) -> Vec<f64> {
+            self.fit_with(dataset, epochs, batch_size, Loss::Mse, &mut Optimizer::adam(0.001))
+        }
+
+        pub fn fit_with(&mut self, dataset: &Dataset, epochs: usize, batch_size: usize,
+                        loss: Loss, optimizer: &mut Optimizer) -> Vec<f64> {
+            let mut rng = thread_rng();
+            let mut indices: Vec<usize> = (0..dataset.len()).collect();
+            let mut history = Vec::new();
+            for _ in 0..epochs {
+                indices.shuffle(&mut rng);
+                let mut sum = 0.;
+                let mut batches = 0;
+                for chunk in indices.chunks(batch_size.max(1)) {
+                    let (input, target) = dataset.batch(chunk);
+                    sum += self.train_batch(&input, &target, loss, optimizer);
+                    batches += 1;
+                }
+                history.push(sum / batches.max(1) as f64);
+            }
+            return history;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::{Activation, Init, Layer};
+        use rand::rngs::StdRng;
+
+        // Compares Model::backward with central finite differences of the
+        // loss, for every parameter of a small dense model.
+        fn check(hidden: Activation, output: Activation, loss: Loss) {
+            let name = format!("{}, {}, {:?}", hidden.name(), output.name(), loss);
+            let mut rng = StdRng::seed_from_u64(7);
+            let mut model = Model::new();
+            model.add_layer(Layer::new_init(4, 5, hidden, Init::XavierUniform, 1)).unwrap();
+            model.add_layer(Layer::new_init(5, 3, output, Init::XavierUniform, 2)).unwrap();
+            let parameters = DVector::from_fn(model.num_parameters(), |_, _| rng.gen_range(-1., 1.));
+            model.set_parameters(&parameters);
+            let input = DMatrix::from_fn(4, 6, |_, _| rng.gen_range(-1., 1.));
+            let target = DMatrix::from_fn(3, 6, |_, _| rng.gen_range(0.1, 0.9));
+
+            let trace = model.forward_trace(&input);
+            let out = &trace.last().unwrap().1;
+            let gradient = model.backward(&input, &trace, loss.gradient(out, &target));
+            assert_eq!(gradient.len(), parameters.len());
+
+            let h = 1e-6;
+            for i in 0..parameters.len() {
+                let mut p = parameters.clone();
+                p[i] += h;
+                model.set_parameters(&p);
+                let plus = loss.value(&model.get_result(&input), &target);
+                p[i] -= 2. * h;
+                model.set_parameters(&p);
+                let minus = loss.value(&model.get_result(&input), &target);
+                let numeric = (plus - minus) / (2. * h);
+                assert!((numeric - gradient[i]).abs() < 1e-6 * (1. + numeric.abs()),
+                        "{}: parameter {}, backward {}, finite differences {}",
+                        name, i, gradient[i], numeric);
+            }
+        }
+
+        #[test]
+        fn mse_tanh() {
+            check(Activation::Tanh, Activation::Tanh, Loss::Mse);
+        }
+
+        #[test]
+        fn mse_sigmoid() {
+            check(Activation::Sigmoid, Activation::Sigmoid, Loss::Mse);
+        }
+
+        #[test]
+        fn mse_softmax() {
+            check(Activation::Tanh, Activation::Softmax, Loss::Mse);
+        }
+
+        // y = sin(x0) * x1 on a fixed grid, small enough to fit in a few epochs
+        fn lowers_the_loss(mut optimizer: Optimizer) {
+            let mut dataset = Dataset::new();
+            for i in 0..8 {
+                for j in 0..8 {
+                    let x0 = i as f64 / 4. - 1.;
+                    let x1 = j as f64 / 4. - 1.;
+                    dataset.push(DVector::from_vec(vec![x0, x1]), DVector::from_vec(vec![x0.sin() * x1]));
+                }
+            }
+            let mut model = Model::new();
+            model.add_layer(Layer::new_init(2, 6, Activation::Tanh, Init::XavierUniform, 3)).unwrap();
+            model.add_layer(Layer::new_init(6, 1, Activation::Tanh, Init::XavierUniform, 4)).unwrap();
+            let before = model.loss(&dataset, Loss::Mse);
+            model.fit_with(&dataset, 50, 8, Loss::Mse, &mut optimizer);
+            let after = model.loss(&dataset, Loss::Mse);
+            assert!(after < 0.5 * before, "{:?}: loss {} -> {}", optimizer.method, before, after);
+        }
+
+        #[test]
+        fn sgd_lowers_the_loss() {
+            lowers_the_loss(Optimizer::sgd(0.05));
+        }
+
+        #[test]
+        fn momentum_lowers_the_loss() {
+            lowers_the_loss(Optimizer::momentum(0.01));
+        }
+
+        #[test]
+        fn adam_lowers_the_loss() {
+            lowers_the_loss(Optimizer::adam(0.01));
+        }
+    }
+}
@@ -1,18 +1,38 @@
 extern crate nalgebra as na;
 
+pub(crate) mod neat;
+pub(crate) mod save;
+pub(crate) mod grad;
+pub(crate) mod quant;
+pub(crate) mod codegen;
 
 pub mod model {
-    use na::DMatrix;
+    use na::{DMatrix, DVector, Real};
     use rand::prelude::*;
+    use rand::distributions::{Normal, StandardNormal};
+    use rand::rngs::StdRng;
+    use std::cell::{RefCell, RefMut};
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fmt;
+    use std::ops::Range;
+    use std::sync::{Arc, RwLock};
     #[derive(Debug)]
     pub enum Activation {
         Relu,
         Sigmoid,
         Tanh,
         ArcTan,
-        Binaty,
+        Step,
         Logistic(f64, f64, f64),
         Softsign,
+        Linear,
+        LeakyRelu(f64),
+        Elu(f64),
+        // across the neurons of the layer
+        Softmax,
+        // registered with register_activation
+        Custom(String),
     }
 
     impl Clone for Activation {
@@ -22,17 +42,246 @@ pub mod model {
                 Activation::Sigmoid => Activation::Sigmoid,
                 Activation::Tanh => Activation::Tanh,
                 Activation::ArcTan => Activation::ArcTan,
-                Activation::Binaty => Activation::Binaty,
+                Activation::Step => Activation::Step,
                 Activation::Logistic(l, k, x0) => Activation::Logistic(l, k, x0),
                 Activation::Softsign => Activation::Softsign,
+                Activation::Linear => Activation::Linear,
+                Activation::LeakyRelu(alpha) => Activation::LeakyRelu(alpha),
+                Activation::Elu(alpha) => Activation::Elu(alpha),
+                Activation::Softmax => Activation::Softmax,
+                Activation::Custom(ref name) => Activation::Custom(name.clone()),
             }
         }
     }
 
-    pub fn signoid(matrix: &DMatrix<f64>) -> DMatrix<f64> {
+    // A user defined activation. Without derivative the gradient is
+    // estimated with finite differences.
+    pub struct CustomActivation {
+        pub function: Box<dyn Fn(f64) -> f64 + Send + Sync>,
+        pub derivative: Option<Box<dyn Fn(f64) -> f64 + Send + Sync>>,
+    }
+
+    lazy_static! {
+        static ref CUSTOM_ACTIVATIONS: RwLock<HashMap<String, Arc<CustomActivation>>> = RwLock::new(HashMap::new());
+    }
+
+    // Make Activation::Custom(name) usable, also by saved models naming it.
+    pub fn register_activation<F, D>(name: &str, function: F, derivative: Option<D>)
+        where F: Fn(f64) -> f64 + Send + Sync + 'static,
+              D: Fn(f64) -> f64 + Send + Sync + 'static {
+        let custom = CustomActivation {
+            function: Box::new(function),
+            derivative: derivative.map(|d| Box::new(d) as Box<dyn Fn(f64) -> f64 + Send + Sync>),
+        };
+        CUSTOM_ACTIVATIONS.write().unwrap().insert(name.to_string(), Arc::new(custom));
+    }
+
+    pub fn is_registered(name: &str) -> bool {
+        CUSTOM_ACTIVATIONS.read().unwrap().contains_key(name)
+    }
+
+    fn custom_activation(name: &str) -> Arc<CustomActivation> {
+        match CUSTOM_ACTIVATIONS.read().unwrap().get(name) {
+            Some(c) => Arc::clone(c),
+            None => panic!("activation {} is not registered", name),
+        }
+    }
+
+    // arguments of a name like "logistic(1,2,3)"
+    fn arguments(name: &str, prefix: &str, count: usize) -> Option<Vec<f64>> {
+        if !name.starts_with(prefix) || !name.ends_with(')') {
+            return None;
+        }
+        let args: Vec<f64> = name[prefix.len()..name.len() - 1].split(',')
+            .filter_map(|a| a.parse().ok())
+            .collect();
+        if args.len() == count {
+            Some(args)
+        } else {
+            None
+        }
+    }
+
+    impl Activation {
+        pub fn compute(&self, matrix: &DMatrix<f64>) -> DMatrix<f64> {
+            match *self {
+                Activation::Sigmoid => sigmoid(matrix),
+                Activation::Relu => relu(matrix),
+                Activation::Tanh => tanh(matrix),
+                Activation::ArcTan => arctan(matrix),
+                Activation::Step => step(matrix),
+                Activation::Logistic(l, k, x0) => logistic(matrix, l, k, x0),
+                Activation::Softsign => softsign(matrix),
+                Activation::Linear => matrix.clone(),
+                Activation::LeakyRelu(alpha) => leaky_relu(matrix, alpha),
+                Activation::Elu(alpha) => elu(matrix, alpha),
+                Activation::Softmax => softmax(matrix),
+                Activation::Custom(ref name) => {
+                    let f = custom_activation(name);
+                    matrix.map(|x| (f.function)(x))
+                },
+            }
+        }
+
+        pub fn name(&self) -> String {
+            match *self {
+                Activation::Relu => "relu".to_string(),
+                Activation::Sigmoid => "sigmoid".to_string(),
+                Activation::Tanh => "tanh".to_string(),
+                Activation::ArcTan => "arctan".to_string(),
+                Activation::Step => "step".to_string(),
+                Activation::Logistic(l, k, x0) => format!("logistic({},{},{})", l, k, x0),
+                Activation::Softsign => "softsign".to_string(),
+                Activation::Linear => "linear".to_string(),
+                Activation::LeakyRelu(alpha) => format!("leaky_relu({})", alpha),
+                Activation::Elu(alpha) => format!("elu({})", alpha),
+                Activation::Softmax => "softmax".to_string(),
+                Activation::Custom(ref name) => format!("custom({})", name),
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Activation> {
+            match name {
+                "relu" => Some(Activation::Relu),
+                "sigmoid" => Some(Activation::Sigmoid),
+                "tanh" => Some(Activation::Tanh),
+                "arctan" => Some(Activation::ArcTan),
+                // older name of the step function
+                "step" | "binary" => Some(Activation::Step),
+                "softsign" => Some(Activation::Softsign),
+                "linear" => Some(Activation::Linear),
+                "softmax" => Some(Activation::Softmax),
+                _ if name.starts_with("custom(") && name.ends_with(')') => {
+                    Some(Activation::Custom(name[7..name.len() - 1].to_string()))
+                },
+                _ => {
+                    if let Some(a) = arguments(name, "logistic(", 3) {
+                        Some(Activation::Logistic(a[0], a[1], a[2]))
+                    } else if let Some(a) = arguments(name, "leaky_relu(", 1) {
+                        Some(Activation::LeakyRelu(a[0]))
+                    } else if let Some(a) = arguments(name, "elu(", 1) {
+                        Some(Activation::Elu(a[0]))
+                    } else {
+                        None
+                    }
+                },
+            }
+        }
+
+        // Derivative of the activation for the preactivation `pre`, which
+        // gave `out`. Softmax mixes the neurons and has no such derivative,
+        // use backward for it.
+        pub fn derivative(&self, pre: &DMatrix<f64>, out: &DMatrix<f64>) -> DMatrix<f64> {
+            match *self {
+                Activation::Sigmoid => out.map(|s| s * (1. - s)),
+                Activation::Relu => pre.map(|x| if x < 0. { 0. } else { 1. }),
+                Activation::Tanh => out.map(|y| 1. - y * y),
+                Activation::ArcTan => pre.map(|x| 1. / (1. + x * x)),
+                Activation::Step => pre.map(|_| 0.),
+                Activation::Logistic(l, k, _) => out.map(|y| k * y * (1. - y / l)),
+                Activation::Softsign => pre.map(|x| 1. / (1. + x.abs()).powi(2)),
+                Activation::Linear => pre.map(|_| 1.),
+                Activation::LeakyRelu(alpha) => pre.map(|x| if x < 0. { alpha } else { 1. }),
+                Activation::Elu(alpha) => pre.zip_map(out, |x, y| if x < 0. { y + alpha } else { 1. }),
+                Activation::Softmax => panic!("softmax has no element-wise derivative"),
+                Activation::Custom(ref name) => {
+                    let custom = custom_activation(name);
+                    match custom.derivative {
+                        Some(ref d) => pre.map(|x| d(x)),
+                        None => pre.map(|x| ((custom.function)(x + 1e-6) - (custom.function)(x - 1e-6)) / 2e-6),
+                    }
+                },
+            }
+        }
+
+        // Gradient of the loss for the preactivation from the gradient for the output.
+        pub fn backward(&self, pre: &DMatrix<f64>, out: &DMatrix<f64>, grad: &DMatrix<f64>) -> DMatrix<f64> {
+            match *self {
+                Activation::Softmax => {
+                    // dpre_i = y_i * (g_i - sum_j g_j * y_j) for each column
+                    let mut r = grad.clone();
+                    for j in 0..r.ncols() {
+                        let dot = grad.column(j).dot(&out.column(j));
+                        for i in 0..r.nrows() {
+                            r[(i, j)] = out[(i, j)] * (grad[(i, j)] - dot);
+                        }
+                    }
+                    r
+                },
+                _ => grad.component_mul(&self.derivative(pre, out)),
+            }
+        }
+
+        pub fn apply(&self, x: f64) -> f64 {
+            self.compute(&DMatrix::from_element(1, 1, x))[0]
+        }
+
+        // Same as compute, in place and for any scalar type. The custom
+        // activations go through f64.
+        pub fn compute_mut<N: Real>(&self, matrix: &mut DMatrix<N>) {
+            let (zero, one) = (N::zero(), N::one());
+            match *self {
+                Activation::Sigmoid => matrix.apply(|x| one / (one + (-x).exp())),
+                Activation::Relu => matrix.apply(|x| if x < zero { zero } else { x }),
+                Activation::Tanh => matrix.apply(|x| x.tanh()),
+                Activation::ArcTan => matrix.apply(|x| x.atan()),
+                Activation::Step => matrix.apply(|x| if x < zero { zero } else { one }),
+                Activation::Logistic(l, k, x0) => {
+                    let (l, k, x0): (N, N, N) = (na::convert(l), na::convert(k), na::convert(x0));
+                    matrix.apply(|x| l / (one + (-k * (x - x0)).exp()))
+                },
+                Activation::Softsign => matrix.apply(|x| x / (one + x.abs())),
+                Activation::Linear => {},
+                Activation::LeakyRelu(alpha) => {
+                    let alpha: N = na::convert(alpha);
+                    matrix.apply(|x| if x < zero { alpha * x } else { x })
+                },
+                Activation::Elu(alpha) => {
+                    let alpha: N = na::convert(alpha);
+                    matrix.apply(|x| if x < zero { alpha * (x.exp() - one) } else { x })
+                },
+                Activation::Softmax => {
+                    for mut col in matrix.column_iter_mut() {
+                        let max = col.max();
+                        col.apply(|x| (x - max).exp());
+                        let sum = col.sum();
+                        col /= sum;
+                    }
+                },
+                Activation::Custom(ref name) => {
+                    let f = custom_activation(name);
+                    matrix.apply(|x| na::convert((f.function)(to_f64(x))))
+                },
+            }
+        }
+    }
+
+    fn to_f64<N: Real>(x: N) -> f64 {
+        na::try_convert(x).unwrap()
+    }
+
+    // Anything that can drive a car: turn the observation into [throttle, steering].
+    pub trait Controller {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64>;
+
+        // called at the start of every episode
+        fn reset_state(&self) {}
+
+        // number of values the controller reads, when it is known
+        fn input_size(&self) -> Option<u32> {
+            None
+        }
+
+        // the network that computes the result, to look inside it
+        fn network(&self) -> Option<&Model> {
+            None
+        }
+    }
+
+    pub fn sigmoid(matrix: &DMatrix<f64>) -> DMatrix<f64> {
         let mut r: DMatrix<f64> = matrix.clone();
         for a in r.iter_mut() {
-            *a = 1.0/(1.0 + a.exp());
+            *a = 1.0/(1.0 + (-*a).exp());
         }
         return r;
     }
@@ -63,7 +312,7 @@ pub mod model {
         return r;
     }
 
-    pub fn binary(matrix: &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn step(matrix: &DMatrix<f64>) -> DMatrix<f64> {
         let mut r: DMatrix<f64> = matrix.clone();
         for a in r.iter_mut() {
             if *a < 0. {
@@ -91,60 +340,628 @@ pub mod model {
         return r;
     }
 
+    pub fn leaky_relu(matrix: &DMatrix<f64>, alpha: f64) -> DMatrix<f64> {
+        let mut r: DMatrix<f64> = matrix.clone();
+        for a in r.iter_mut() {
+            if *a < 0. {
+                *a = alpha * *a;
+            }
+        }
+        return r;
+    }
+
+    pub fn elu(matrix: &DMatrix<f64>, alpha: f64) -> DMatrix<f64> {
+        let mut r: DMatrix<f64> = matrix.clone();
+        for a in r.iter_mut() {
+            if *a < 0. {
+                *a = alpha * (a.exp() - 1.);
+            }
+        }
+        return r;
+    }
+
+    // every column sums to 1
+    pub fn softmax(matrix: &DMatrix<f64>) -> DMatrix<f64> {
+        let mut r: DMatrix<f64> = matrix.clone();
+        for mut col in r.column_iter_mut() {
+            let max = col.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.;
+            for a in col.iter_mut() {
+                *a = (*a - max).exp();
+                sum += *a;
+            }
+            for a in col.iter_mut() {
+                *a /= sum;
+            }
+        }
+        return r;
+    }
+
+    // How the weights of a layer are drawn when it is created.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Init {
+        // weights and biases uniform in [0, 1[
+        Uniform,
+        XavierUniform,
+        XavierNormal,
+        // normal with a variance of 2 / input, for relu layers
+        He,
+        Orthogonal,
+        Zeros,
+        Constant(f64),
+    }
+
+    impl Init {
+        pub fn name(&self) -> String {
+            match *self {
+                Init::Uniform => "uniform".to_string(),
+                Init::XavierUniform => "xavier_uniform".to_string(),
+                Init::XavierNormal => "xavier_normal".to_string(),
+                Init::He => "he".to_string(),
+                Init::Orthogonal => "orthogonal".to_string(),
+                Init::Zeros => "zeros".to_string(),
+                Init::Constant(c) => format!("constant({})", c),
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Init> {
+            match name {
+                "uniform" => Some(Init::Uniform),
+                "xavier_uniform" => Some(Init::XavierUniform),
+                "xavier_normal" => Some(Init::XavierNormal),
+                "he" => Some(Init::He),
+                "orthogonal" => Some(Init::Orthogonal),
+                "zeros" => Some(Init::Zeros),
+                _ => arguments(name, "constant(", 1).map(|a| Init::Constant(a[0])),
+            }
+        }
+
+        // weights (neuron x input) and biases drawn with the seeded generator
+        pub fn generate(&self, input: usize, neuron: usize, seed: u64) -> (DMatrix<f64>, DMatrix<f64>) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let fan = (input + neuron) as f64;
+            match *self {
+                Init::Uniform => {
+                    let w = DMatrix::from_fn(neuron, input, |_, _| rng.gen::<f64>());
+                    let b = DMatrix::from_fn(neuron, 1, |_, _| rng.gen::<f64>());
+                    (w, b)
+                },
+                Init::XavierUniform => {
+                    let limit = (6. / fan).sqrt();
+                    (DMatrix::from_fn(neuron, input, |_, _| rng.gen_range(-limit, limit)), DMatrix::zeros(neuron, 1))
+                },
+                Init::XavierNormal => {
+                    let normal = Normal::new(0., (2. / fan).sqrt());
+                    (DMatrix::from_fn(neuron, input, |_, _| rng.sample(normal)), DMatrix::zeros(neuron, 1))
+                },
+                Init::He => {
+                    let normal = Normal::new(0., (2. / input.max(1) as f64).sqrt());
+                    (DMatrix::from_fn(neuron, input, |_, _| rng.sample(normal)), DMatrix::zeros(neuron, 1))
+                },
+                Init::Orthogonal => {
+                    // Q of the QR decomposition of a gaussian matrix, its
+                    // columns are orthonormal
+                    let rows = neuron.max(input);
+                    let cols = neuron.min(input);
+                    let a = DMatrix::from_fn(rows, cols, |_, _| rng.sample(StandardNormal));
+                    let qr = a.qr();
+                    let mut q = qr.q();
+                    let r = qr.r();
+                    for j in 0..cols {
+                        if r[(j, j)] < 0. {
+                            for i in 0..rows {
+                                q[(i, j)] = -q[(i, j)];
+                            }
+                        }
+                    }
+                    let w = if neuron >= input { q } else { q.transpose() };
+                    (w, DMatrix::zeros(neuron, 1))
+                },
+                Init::Zeros => (DMatrix::zeros(neuron, input), DMatrix::zeros(neuron, 1)),
+                Init::Constant(c) => (DMatrix::from_element(neuron, input, c), DMatrix::from_element(neuron, 1, c)),
+            }
+        }
+    }
+
+    // Geometry of a convolution: the input is `channels` maps of
+    // `height` x `width` values, one map after the other and row by row.
+    // `filters` kernels of `kernel` x `kernel` slide by `stride`, then each
+    // output map is max pooled by blocks of `pool` x `pool` (1: no pooling).
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Conv {
+        pub channels: u32,
+        pub height: u32,
+        pub width: u32,
+        pub filters: u32,
+        pub kernel: u32,
+        pub stride: u32,
+        pub pool: u32,
+    }
+
+    impl Conv {
+        // the radar of the car: one channel of 8 x 7
+        pub fn radar(filters: u32, kernel: u32, stride: u32, pool: u32) -> Conv {
+            Conv {
+                channels: 1,
+                height: 8,
+                width: 7,
+                filters: filters,
+                kernel: kernel,
+                stride: stride,
+                pool: pool,
+            }
+        }
+
+        pub fn is_valid(&self) -> bool {
+            self.channels > 0 && self.filters > 0 && self.kernel > 0 && self.stride > 0 && self.pool > 0
+                && self.kernel <= self.height && self.kernel <= self.width
+                && self.pooled().0 > 0 && self.pooled().1 > 0
+        }
+
+        pub fn input_size(&self) -> u32 {
+            self.channels * self.height * self.width
+        }
+
+        // height and width of the maps after the convolution
+        pub fn convolved(&self) -> (u32, u32) {
+            ((self.height - self.kernel) / self.stride + 1, (self.width - self.kernel) / self.stride + 1)
+        }
+
+        // height and width of the maps after the pooling
+        pub fn pooled(&self) -> (u32, u32) {
+            let (h, w) = self.convolved();
+            (h / self.pool, w / self.pool)
+        }
+
+        pub fn output_size(&self) -> u32 {
+            let (h, w) = self.pooled();
+            self.filters * h * w
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Kind {
+        Dense,
+        // h = a(w x + u h + b)
+        Elman,
+        // gated recurrent unit, a is the activation of the candidate state
+        Gru,
+        // w has one row per filter, b one value per filter
+        Conv(Conv),
+    }
+
+    impl Kind {
+        pub fn name(&self) -> String {
+            match *self {
+                Kind::Dense => "dense".to_string(),
+                Kind::Elman => "elman".to_string(),
+                Kind::Gru => "gru".to_string(),
+                Kind::Conv(c) => format!("conv({},{},{},{},{},{},{})", c.channels, c.height, c.width,
+                                         c.filters, c.kernel, c.stride, c.pool),
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Kind> {
+            match name {
+                "dense" => Some(Kind::Dense),
+                "elman" => Some(Kind::Elman),
+                "gru" => Some(Kind::Gru),
+                _ => {
+                    let a = arguments(name, "conv(", 7)?;
+                    if a.iter().any(|v| *v < 0. || v.fract() != 0.) {
+                        return None;
+                    }
+                    let conv = Conv {
+                        channels: a[0] as u32,
+                        height: a[1] as u32,
+                        width: a[2] as u32,
+                        filters: a[3] as u32,
+                        kernel: a[4] as u32,
+                        stride: a[5] as u32,
+                        pool: a[6] as u32,
+                    };
+                    if conv.is_valid() { Some(Kind::Conv(conv)) } else { None }
+                },
+            }
+        }
+    }
+
+    // The parameters are f64 for training, a trained layer can be cast to
+    // f32 for a faster and lighter evaluation.
     #[derive(Debug)]
-    pub struct Layer {
+    pub struct Layer<N: Real = f64> {
         input: u32,
         neuron: u32,
-        w: DMatrix<f64>,
-        b: DMatrix<f64>,
+        w: DMatrix<N>,
+        b: DMatrix<N>,
         a: Activation,
+        init: Init,
+        seed: u64,
+        kind: Kind,
+        // parameters of the recurrent layers besides w and b:
+        // elman: u, gru: u, wz, uz, bz, wr, ur, br
+        extra: Vec<(&'static str, DMatrix<N>)>,
+        // hidden state of the recurrent layers, kept between two get_result
+        state: RefCell<DMatrix<N>>,
+        // one flag per parameter, in the order of params(): a pruned
+        // parameter stays at zero. Empty when nothing is pruned.
+        pruned: Vec<bool>,
     }
 
-    impl Clone for Layer {
-        fn clone(&self) -> Layer {
+    // A clone has the same weights but a fresh state.
+    impl<N: Real> Clone for Layer<N> {
+        fn clone(&self) -> Layer<N> {
             Layer {
                 input: self.input,
                 neuron: self.neuron,
                 w: self.w.clone(),
                 b: self.b.clone(),
                 a: self.a.clone(),
+                init: self.init,
+                seed: self.seed,
+                kind: self.kind,
+                extra: self.extra.clone(),
+                state: RefCell::new(DMatrix::zeros(self.neuron as usize, 1)),
+                pruned: self.pruned.clone(),
             }
         }
     }
 
     impl Layer {
         pub fn new_random(input: u32, neuron: u32, activation: Activation) -> Layer {
+            Layer::new_init(input, neuron, activation, Init::Uniform, rand::random())
+        }
+
+        // The init and the seed are kept with the layer and saved with it.
+        pub fn new_init(input: u32, neuron: u32, activation: Activation, init: Init, seed: u64) -> Layer {
+            Layer::new_kind(Kind::Dense, input, neuron, activation, init, seed)
+        }
+
+        // The sizes of a convolution come from its geometry, use new_conv.
+        pub fn new_kind(kind: Kind, input: u32, neuron: u32, activation: Activation, init: Init, seed: u64) -> Layer {
+            let (i, n) = (input as usize, neuron as usize);
+            let (w, b) = match kind {
+                Kind::Conv(c) => {
+                    assert!(c.is_valid(), "invalid convolution {}", kind.name());
+                    assert!(c.input_size() == input && c.output_size() == neuron,
+                            "a {} has {} inputs and {} outputs", kind.name(), c.input_size(), c.output_size());
+                    init.generate((c.channels * c.kernel * c.kernel) as usize, c.filters as usize, seed)
+                },
+                _ => init.generate(i, n, seed),
+            };
+            let mut extra = Vec::new();
+            // every matrix gets its own seed derived from the one of the layer
+            match kind {
+                Kind::Dense | Kind::Conv(_) => {},
+                Kind::Elman => {
+                    extra.push(("u", init.generate(n, n, seed.wrapping_add(1)).0));
+                },
+                Kind::Gru => {
+                    extra.push(("u", init.generate(n, n, seed.wrapping_add(1)).0));
+                    let (wz, bz) = init.generate(i, n, seed.wrapping_add(2));
+                    extra.push(("wz", wz));
+                    extra.push(("uz", init.generate(n, n, seed.wrapping_add(3)).0));
+                    extra.push(("bz", bz));
+                    let (wr, br) = init.generate(i, n, seed.wrapping_add(4));
+                    extra.push(("wr", wr));
+                    extra.push(("ur", init.generate(n, n, seed.wrapping_add(5)).0));
+                    extra.push(("br", br));
+                },
+            }
             Layer {
                 input: input,
                 neuron: neuron,
-                w: DMatrix::new_random(neuron as usize, input as usize),
-                b: DMatrix::new_random(neuron as usize, 1),
+                w: w,
+                b: b,
                 a: activation,
+                init: init,
+                seed: seed,
+                kind: kind,
+                extra: extra,
+                state: RefCell::new(DMatrix::zeros(n, 1)),
+                pruned: Vec::new(),
+            }
+        }
+
+        pub fn new_conv(conv: Conv, activation: Activation, init: Init, seed: u64) -> Layer {
+            Layer::new_kind(Kind::Conv(conv), conv.input_size(), conv.output_size(), activation, init, seed)
+        }
+    }
+
+    impl<N: Real> Layer<N> {
+        pub fn input(&self) -> u32 {
+            self.input
+        }
+
+        pub fn neuron(&self) -> u32 {
+            self.neuron
+        }
+
+        pub fn activation(&self) -> &Activation {
+            &self.a
+        }
+
+        pub fn init(&self) -> (Init, u64) {
+            (self.init, self.seed)
+        }
+
+        pub fn kind(&self) -> Kind {
+            self.kind
+        }
+
+        pub fn reset_state(&self) {
+            *self.state.borrow_mut() = DMatrix::zeros(self.neuron as usize, 1);
+        }
+
+        // parameter matrices of the layer with their names, in the order of Model::parameters
+        pub fn params(&self) -> Vec<(&'static str, &DMatrix<N>)> {
+            let mut r = vec![("w", &self.w), ("b", &self.b)];
+            for (name, m) in self.extra.iter() {
+                r.push((*name, m));
+            }
+            return r;
+        }
+
+        pub fn params_mut(&mut self) -> Vec<(&'static str, &mut DMatrix<N>)> {
+            let mut r = vec![("w", &mut self.w), ("b", &mut self.b)];
+            for (name, m) in self.extra.iter_mut() {
+                r.push((*name, m));
+            }
+            return r;
+        }
+
+        fn extra(&self, name: &str) -> &DMatrix<N> {
+            &self.extra.iter().find(|e| e.0 == name).unwrap().1
+        }
+
+        pub fn num_parameters(&self) -> usize {
+            self.params().iter().map(|p| p.1.len()).sum()
+        }
+
+        // parameter i, in the order of params(), is set to zero for good
+        pub fn prune(&mut self, i: usize) {
+            if self.pruned.is_empty() {
+                self.pruned = vec![false; self.num_parameters()];
+            }
+            self.pruned[i] = true;
+            self.apply_mask();
+        }
+
+        pub fn is_pruned(&self, i: usize) -> bool {
+            !self.pruned.is_empty() && self.pruned[i]
+        }
+
+        // put the pruned parameters back to zero after a change
+        pub fn apply_mask(&mut self) {
+            if self.pruned.is_empty() {
+                return;
+            }
+            let pruned = std::mem::replace(&mut self.pruned, Vec::new());
+            let mut i = 0;
+            for (_, m) in self.params_mut() {
+                for v in m.iter_mut() {
+                    if pruned[i] {
+                        *v = N::zero();
+                    }
+                    i += 1;
+                }
+            }
+            self.pruned = pruned;
+        }
+
+        // One observation per column of the input, one output per column.
+        // A recurrent layer keeps one state per column.
+        pub fn get_result(&self, input: &DMatrix<N>) -> DMatrix<N> {
+            match self.kind {
+                Kind::Dense => {
+                    let mut output = affine(&self.w, input, &self.b);
+                    self.a.compute_mut(&mut output);
+                    output
+                },
+                Kind::Elman => {
+                    let mut h = self.state(input.ncols());
+                    let mut output = affine(&self.w, input, &self.b) + self.extra("u") * &*h;
+                    self.a.compute_mut(&mut output);
+                    *h = output.clone();
+                    output
+                },
+                Kind::Gru => {
+                    let mut h = self.state(input.ncols());
+                    let mut z = affine(self.extra("wz"), input, self.extra("bz")) + self.extra("uz") * &*h;
+                    Activation::Sigmoid.compute_mut(&mut z);
+                    let mut r = affine(self.extra("wr"), input, self.extra("br")) + self.extra("ur") * &*h;
+                    Activation::Sigmoid.compute_mut(&mut r);
+                    let mut candidate = affine(&self.w, input, &self.b) + self.extra("u") * r.component_mul(&h);
+                    self.a.compute_mut(&mut candidate);
+                    let keep = z.map(|z| N::one() - z).component_mul(&h);
+                    *h = keep + z.component_mul(&candidate);
+                    h.clone()
+                },
+                Kind::Conv(c) => {
+                    let mut output = DMatrix::zeros(self.neuron as usize, input.ncols());
+                    for j in 0..input.ncols() {
+                        let mut maps = self.convolve(&c, input, j);
+                        self.a.compute_mut(&mut maps);
+                        output.set_column(j, &pool(&c, &maps));
+                    }
+                    output
+                },
+            }
+        }
+
+        // Same as get_result, written into `output`. A dense layer does not
+        // allocate once `output` has the right shape.
+        pub fn get_result_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+            match self.kind {
+                Kind::Dense => {
+                    if output.shape() != (self.neuron as usize, input.ncols()) {
+                        *output = DMatrix::zeros(self.neuron as usize, input.ncols());
+                    }
+                    self.w.mul_to(input, output);
+                    for mut col in output.column_iter_mut() {
+                        col += &self.b;
+                    }
+                    self.a.compute_mut(output);
+                },
+                _ => *output = self.get_result(input),
+            }
+        }
+
+        // hidden state for a batch of `columns` observations, a new batch
+        // size starts from zero
+        fn state(&self, columns: usize) -> RefMut<'_, DMatrix<N>> {
+            let mut h = self.state.borrow_mut();
+            if h.ncols() != columns {
+                *h = DMatrix::zeros(self.neuron as usize, columns);
+            }
+            return h;
+        }
+
+        // same layer with parameters of another scalar type
+        pub fn cast<M: Real>(&self) -> Layer<M> {
+            Layer {
+                input: self.input,
+                neuron: self.neuron,
+                w: cast(&self.w),
+                b: cast(&self.b),
+                a: self.a.clone(),
+                init: self.init,
+                seed: self.seed,
+                kind: self.kind,
+                extra: self.extra.iter().map(|(name, m)| (*name, cast(m))).collect(),
+                state: RefCell::new(DMatrix::zeros(self.neuron as usize, 1)),
+                pruned: self.pruned.clone(),
+            }
+        }
+
+        // preactivation of the column j of the input: one value per filter
+        // and position, filter after filter and row by row
+        fn convolve(&self, c: &Conv, input: &DMatrix<N>, j: usize) -> DMatrix<N> {
+            let (oh, ow) = c.convolved();
+            let (h, w, k, s) = (c.height as usize, c.width as usize, c.kernel as usize, c.stride as usize);
+            let mut maps = DMatrix::zeros(c.filters as usize * (oh * ow) as usize, 1);
+            for f in 0..c.filters as usize {
+                for y in 0..oh as usize {
+                    for x in 0..ow as usize {
+                        let mut sum = self.b[f];
+                        for channel in 0..c.channels as usize {
+                            for ky in 0..k {
+                                for kx in 0..k {
+                                    let weight = self.w[(f, (channel * k + ky) * k + kx)];
+                                    sum += weight * input[(channel * h * w + (y * s + ky) * w + x * s + kx, j)];
+                                }
+                            }
+                        }
+                        maps[(f * (oh * ow) as usize + y * ow as usize + x, 0)] = sum;
+                    }
+                }
             }
+            return maps;
+        }
+    }
+
+    impl Layer {
+        // Preactivation for a batch of inputs, one per column.
+        pub fn preactivation(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            assert_eq!(self.kind, Kind::Dense, "only dense layers are trained by gradient");
+            affine(&self.w, input, &self.b)
         }
 
-        pub fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
-            let preactivation = &self.w * input + &self.b;
-            match self.a {
-                Activation::Sigmoid => signoid(&preactivation),
-                Activation::Relu => relu(&preactivation),
-                Activation::Tanh => tanh(&preactivation),
-                Activation::ArcTan => arctan(&preactivation),
-                Activation::Binaty => binary(&preactivation),
-                Activation::Logistic(l, k, x0) => logistic(&preactivation, l, k, x0),
-                Activation::Softsign => softsign(&preactivation),
+        // From the gradient of the loss for the output of the layer, the
+        // gradients for w, b and the input of the layer.
+        pub fn backward(&self, input: &DMatrix<f64>, pre: &DMatrix<f64>, out: &DMatrix<f64>,
+                        grad: &DMatrix<f64>) -> (DMatrix<f64>, DMatrix<f64>, DMatrix<f64>) {
+            assert_eq!(self.kind, Kind::Dense, "only dense layers are trained by gradient");
+            let delta = self.a.backward(pre, out, grad);
+            let grad_w = &delta * input.transpose();
+            let grad_b = DMatrix::from_column_slice(self.neuron as usize, 1, delta.column_sum().as_slice());
+            let grad_input = self.w.transpose() * &delta;
+            return (grad_w, grad_b, grad_input);
+        }
+    }
+
+    // w * input + b, with b added to every column
+    fn affine<N: Real>(w: &DMatrix<N>, input: &DMatrix<N>, b: &DMatrix<N>) -> DMatrix<N> {
+        let mut r = w * input;
+        for mut col in r.column_iter_mut() {
+            col += b;
+        }
+        return r;
+    }
+
+    fn cast<N: Real, M: Real>(m: &DMatrix<N>) -> DMatrix<M> {
+        m.map(|x| na::convert(to_f64(x)))
+    }
+
+    // max pooling of the output maps of a convolution
+    fn pool<N: Real>(c: &Conv, maps: &DMatrix<N>) -> DVector<N> {
+        let (oh, ow) = c.convolved();
+        let (ph, pw) = c.pooled();
+        let p = c.pool as usize;
+        let mut pooled = DVector::zeros(c.output_size() as usize);
+        for f in 0..c.filters as usize {
+            for y in 0..ph as usize {
+                for x in 0..pw as usize {
+                    let mut max = maps[f * (oh * ow) as usize + y * p * ow as usize + x * p];
+                    for py in 0..p {
+                        for px in 0..p {
+                            max = max.max(maps[f * (oh * ow) as usize + (y * p + py) * ow as usize + x * p + px]);
+                        }
+                    }
+                    pooled[f * (ph * pw) as usize + y * pw as usize + x] = max;
+                }
+            }
+        }
+        return pooled;
+    }
+
+    #[derive(Debug)]
+    pub enum ModelError {
+        // layer `layer` takes `got` inputs but the layer before has `expected` neurons
+        ShapeMismatch { layer: usize, expected: u32, got: u32 },
+        InputSize { expected: u32, got: u32 },
+        Empty,
+        // the layer can not be quantized or exported
+        NotDense { layer: usize, kind: String },
+        // custom activations only exist in this program, they can not be exported
+        CustomActivation { layer: usize, name: String },
+    }
+
+    impl fmt::Display for ModelError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                ModelError::ShapeMismatch { layer, expected, got } => {
+                    write!(f, "layer {} takes {} inputs but the layer before has {} neurons", layer, got, expected)
+                },
+                ModelError::InputSize { expected, got } => {
+                    write!(f, "the model takes {} inputs instead of {}", got, expected)
+                },
+                ModelError::Empty => write!(f, "the model has no layer"),
+                ModelError::NotDense { layer, ref kind } => {
+                    write!(f, "layer {} is a {} layer, only dense layers are supported", layer, kind)
+                },
+                ModelError::CustomActivation { layer, ref name } => {
+                    write!(f, "layer {} uses the custom activation {}, it can not be exported", layer, name)
+                },
             }
         }
     }
+
+    impl Error for ModelError {}
+
     #[derive(Debug)]
-    pub struct Model {
-        layers: Vec<Layer>,
+    pub struct Model<N: Real = f64> {
+        layers: Vec<Layer<N>>,
+        // the input and the output of every layer at the last get_result,
+        // kept only while recording, for the activation inspector
+        recorded: RefCell<Option<Vec<DMatrix<N>>>>,
     }
 
-    impl Clone for Model {
-        fn clone(&self) -> Model {
+    impl<N: Real> Clone for Model<N> {
+        fn clone(&self) -> Model<N> {
             let mut r = Model {
                 layers: Vec::new(),
+                recorded: RefCell::new(None),
             };
             for a in self.layers.iter() {
                 r.layers.push(a.clone());
@@ -157,6 +974,7 @@ pub mod model {
         pub fn new() -> Model {
             Model {
                 layers: Vec::new(),
+                recorded: RefCell::new(None),
             }
         }
 
@@ -164,40 +982,26 @@ pub mod model {
             let mut model = self.clone();
             for (i, a) in model.layers.iter_mut().enumerate() {
                 let layer_papa = papa.layers.get(i).unwrap();
-                for (j, b) in a.w.iter_mut().enumerate() {
-                    let mut p:f64 = rand::random();
-                    if p < 0.5 {
-                        *b = *layer_papa.w.get(j).unwrap();
-                    }
-                    p = rand::random();
-                    if p < percent {
-                        p = rand::random();
+                for ((_, m), (_, m_papa)) in a.params_mut().into_iter().zip(layer_papa.params().into_iter()) {
+                    for (j, b) in m.iter_mut().enumerate() {
+                        let mut p:f64 = rand::random();
                         if p < 0.5 {
-                            p = rand::random();
-                            *b = *b + p * delta_max;
-                        } else {
-                            p = rand::random();
-                            *b = *b - p * delta_max;
+                            *b = *m_papa.get(j).unwrap();
                         }
-                    }
-                }
-                for (j, b) in a.b.iter_mut().enumerate() {
-                    let mut p:f64 = rand::random();
-                    if p < 0.5 {
-                        *b = *layer_papa.b.get(j).unwrap();
-                    }
-                    p = rand::random();
-                    if p < percent {
                         p = rand::random();
-                        if p < 0.5 {
+                        if p < percent {
                             p = rand::random();
-                            *b = *b + p * delta_max;
-                        } else {
-                            p = rand::random();
-                            *b = *b - p * delta_max;
+                            if p < 0.5 {
+                                p = rand::random();
+                                *b = *b + p * delta_max;
+                            } else {
+                                p = rand::random();
+                                *b = *b - p * delta_max;
+                            }
                         }
                     }
                 }
+                a.apply_mask();
             }
             return model;
         }
@@ -205,46 +1009,327 @@ pub mod model {
         pub fn copy_mut(&self, percent: f64, delta_max: f64) -> Model {
             let mut model = self.clone();
             for a in model.layers.iter_mut() {
-                for b in a.w.iter_mut() {
-                    let mut p:f64 = rand::random();
-                    if p < percent {
-                        p = rand::random();
-                        if p < 0.5 {
-                            p = rand::random();
-                            *b = *b + p * delta_max;
-                        } else {
+                for (_, m) in a.params_mut() {
+                    for b in m.iter_mut() {
+                        let mut p:f64 = rand::random();
+                        if p < percent {
                             p = rand::random();
-                            *b = *b - p * delta_max;
+                            if p < 0.5 {
+                                p = rand::random();
+                                *b = *b + p * delta_max;
+                            } else {
+                                p = rand::random();
+                                *b = *b - p * delta_max;
+                            }
                         }
                     }
                 }
-                for b in a.b.iter_mut() {
-                    let mut p:f64 = rand::random();
-                    if p < percent {
-                        p = rand::random();
-                        if p < 0.5 {
-                            p = rand::random();
-                            *b = *b + p * delta_max;
-                        } else {
-                            p = rand::random();
-                            *b = *b - p * delta_max;
-                        }
+                a.apply_mask();
+            }
+            return model;
+        }
+
+        // Mean absolute difference between the weights of two models with the
+        // same shape, used to tell how far apart two individuals are.
+        pub fn distance(&self, other: &Model) -> f64 {
+            let a = self.parameters();
+            let b = other.parameters();
+            if a.len() == 0 || a.len() != b.len() {
+                return 0.;
+            }
+            return (a - b).abs().sum() / self.num_parameters() as f64;
+        }
+
+    }
+
+    impl<N: Real> Model<N> {
+        pub fn layers(&self) -> &Vec<Layer<N>> {
+            &self.layers
+        }
+
+        // forget the hidden state of the recurrent layers, at the start of an episode
+        pub fn reset_state(&self) {
+            for a in self.layers.iter() {
+                a.reset_state();
+            }
+        }
+
+        pub fn num_parameters(&self) -> usize {
+            self.layers.iter().map(|l| l.num_parameters()).sum()
+        }
+
+        // Every parameter matrix of the model, named like "layer0.w".
+        pub fn named_parameters(&self) -> Vec<(String, &DMatrix<N>)> {
+            let mut r = Vec::new();
+            for (i, a) in self.layers.iter().enumerate() {
+                for (name, m) in a.params() {
+                    r.push((format!("layer{}.{}", i, name), m));
+                }
+            }
+            return r;
+        }
+
+        pub fn named_parameters_mut(&mut self) -> Vec<(String, &mut DMatrix<N>)> {
+            let mut r = Vec::new();
+            for (i, a) in self.layers.iter_mut().enumerate() {
+                for (name, m) in a.params_mut() {
+                    r.push((format!("layer{}.{}", i, name), m));
+                }
+            }
+            return r;
+        }
+
+        // Where each named parameter matrix lives in the vector of parameters().
+        pub fn parameter_slices(&self) -> Vec<(String, Range<usize>)> {
+            let mut r = Vec::new();
+            let mut start = 0;
+            for (name, m) in self.named_parameters() {
+                r.push((name, start..start + m.len()));
+                start += m.len();
+            }
+            return r;
+        }
+
+        // All the parameters of every layer as one vector, in layer order.
+        pub fn parameters(&self) -> DVector<N> {
+            let mut p = Vec::with_capacity(self.num_parameters());
+            for (_, m) in self.named_parameters() {
+                p.extend(m.iter());
+            }
+            return DVector::from_vec(p);
+        }
+
+        pub fn set_parameters(&mut self, parameters: &DVector<N>) {
+            assert_eq!(parameters.len(), self.num_parameters(), "wrong number of parameters for this model");
+            let mut it = parameters.iter();
+            for (_, m) in self.named_parameters_mut() {
+                for b in m.iter_mut() {
+                    *b = *it.next().unwrap();
+                }
+            }
+            for a in self.layers.iter_mut() {
+                a.apply_mask();
+            }
+        }
+
+        // Parameter i of parameters() is set to zero and stays there
+        // through mutation, crossover and set_parameters.
+        pub fn prune(&mut self, i: usize) {
+            let mut start = 0;
+            for a in self.layers.iter_mut() {
+                if i < start + a.num_parameters() {
+                    a.prune(i - start);
+                    return;
+                }
+                start += a.num_parameters();
+            }
+            panic!("parameter {} out of a model of {}", i, start);
+        }
+
+        // indices of the pruned parameters in parameters()
+        pub fn pruned(&self) -> Vec<usize> {
+            let mut r = Vec::new();
+            let mut start = 0;
+            for a in self.layers.iter() {
+                for i in 0..a.num_parameters() {
+                    if a.is_pruned(i) {
+                        r.push(start + i);
                     }
                 }
+                start += a.num_parameters();
             }
-            return model;
+            return r;
         }
 
-        pub fn add_layer(&mut self, layer: Layer) {
+        // The input of the layer must be the output of the last one.
+        pub fn add_layer(&mut self, layer: Layer<N>) -> Result<(), ModelError> {
+            if let Some(last) = self.layers.last() {
+                if last.neuron != layer.input {
+                    return Err(ModelError::ShapeMismatch {
+                        layer: self.layers.len(),
+                        expected: last.neuron,
+                        got: layer.input,
+                    });
+                }
+            }
             self.layers.push(layer);
+            return Ok(());
+        }
+
+        // Check that the model takes observations of this size.
+        pub fn check_input(&self, size: u32) -> Result<(), ModelError> {
+            match self.layers.first() {
+                Some(first) if first.input != size => Err(ModelError::InputSize {
+                    expected: size,
+                    got: first.input,
+                }),
+                Some(_) => Ok(()),
+                None => Err(ModelError::Empty),
+            }
         }
 
-        pub fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
-            let mut temp: DMatrix<f64> = input.clone();
+        // One line per layer with its shape, activation, init and number of
+        // parameters, then the total.
+        pub fn summary(&self) -> String {
+            let mut s = format!("{:<6} {:<20} {:>12} {:<16} {:<16} {:>8}\n", "layer", "kind", "shape", "activation", "init", "params");
+            for (i, a) in self.layers.iter().enumerate() {
+                s.push_str(&format!("{:<6} {:<20} {:>12} {:<16} {:<16} {:>8}\n", i, a.kind.name(),
+                                    format!("{} -> {}", a.input, a.neuron),
+                                    a.a.name(), a.init.name(), a.num_parameters()));
+            }
+            s.push_str(&format!("total: {} parameters\n", self.num_parameters()));
+            return s;
+        }
+
+    }
+
+    // training by gradient, see grad.rs
+    impl Model {
+        // Forward pass on a batch (one observation per column) keeping the
+        // preactivation and the output of every layer for backward.
+        pub fn forward_trace(&self, input: &DMatrix<f64>) -> Vec<(DMatrix<f64>, DMatrix<f64>)> {
+            let mut trace: Vec<(DMatrix<f64>, DMatrix<f64>)> = Vec::new();
             for a in self.layers.iter() {
+                let pre = match trace.last() {
+                    Some(t) => a.preactivation(&t.1),
+                    None => a.preactivation(input),
+                };
+                let out = a.a.compute(&pre);
+                trace.push((pre, out));
+            }
+            return trace;
+        }
+
+        // Gradient of the loss for every parameter, in the order of
+        // parameters(), from the gradient of the loss for the output.
+        pub fn backward(&self, input: &DMatrix<f64>, trace: &Vec<(DMatrix<f64>, DMatrix<f64>)>,
+                        grad_output: DMatrix<f64>) -> DVector<f64> {
+            let mut grads: Vec<(DMatrix<f64>, DMatrix<f64>)> = Vec::new();
+            let mut grad = grad_output;
+            for (i, a) in self.layers.iter().enumerate().rev() {
+                let layer_input = if i == 0 { input } else { &trace[i - 1].1 };
+                let (grad_w, grad_b, grad_input) = a.backward(layer_input, &trace[i].0, &trace[i].1, &grad);
+                grads.push((grad_w, grad_b));
+                grad = grad_input;
+            }
+            let mut g = Vec::with_capacity(self.num_parameters());
+            for (grad_w, grad_b) in grads.iter().rev() {
+                g.extend(grad_w.iter());
+                g.extend(grad_b.iter());
+            }
+            return DVector::from_vec(g);
+        }
+
+    }
+
+    impl<N: Real> Model<N> {
+        // Batched inference: one observation per column of the input, one
+        // [throttle, steering] per column of the result.
+        pub fn get_result(&self, input: &DMatrix<N>) -> DMatrix<N> {
+            let mut recorded = self.recorded.borrow_mut();
+            if let Some(ref mut outputs) = *recorded {
+                outputs.clear();
+                outputs.push(input.clone());
+            }
+            let mut layers = self.layers.iter();
+            let mut temp = match layers.next() {
+                Some(a) => a.get_result(input),
+                None => return input.clone(),
+            };
+            for a in layers {
+                if let Some(ref mut outputs) = *recorded {
+                    outputs.push(temp.clone());
+                }
                 temp = a.get_result(&temp);
             }
+            if let Some(ref mut outputs) = *recorded {
+                outputs.push(temp.clone());
+            }
             return temp;
         }
+
+        // While recording, get_result keeps its input and the output of
+        // every layer, read them with recorded(). get_result_with does not
+        // record.
+        pub fn record(&self, on: bool) {
+            *self.recorded.borrow_mut() = if on { Some(Vec::new()) } else { None };
+        }
+
+        // the input then the output of every layer at the last get_result,
+        // empty before the first one
+        pub fn recorded(&self) -> Option<Vec<DMatrix<N>>> {
+            self.recorded.borrow().clone()
+        }
+
+        pub fn scratch(&self) -> Scratch<N> {
+            Scratch {
+                outputs: self.layers.iter().map(|a| DMatrix::zeros(a.neuron as usize, 1)).collect(),
+            }
+        }
+
+        // Same as get_result, the output of every layer is written in the
+        // buffers of `scratch`: once they have the shape of the batch, the
+        // dense layers evaluate without allocating.
+        pub fn get_result_with<'a>(&self, input: &DMatrix<N>, scratch: &'a mut Scratch<N>) -> &'a DMatrix<N> {
+            assert!(!self.layers.is_empty(), "the model has no layer");
+            if scratch.outputs.len() != self.layers.len() {
+                *scratch = self.scratch();
+            }
+            for (i, a) in self.layers.iter().enumerate() {
+                let (before, after) = scratch.outputs.split_at_mut(i);
+                let layer_input = if i == 0 { input } else { &before[i - 1] };
+                a.get_result_into(layer_input, &mut after[0]);
+            }
+            return scratch.outputs.last().unwrap();
+        }
+
+        // Same model with parameters of another scalar type, for example
+        // `model.cast::<f32>()` to drive, `cast::<f64>()` to train or save it
+        // again. The init and the seed of every layer are kept.
+        pub fn cast<M: Real>(&self) -> Model<M> {
+            Model {
+                layers: self.layers.iter().map(|a| a.cast()).collect(),
+                recorded: RefCell::new(None),
+            }
+        }
+    }
+
+    // Reusable buffers for Model::get_result_with, one per layer.
+    #[derive(Debug, Clone)]
+    pub struct Scratch<N: Real = f64> {
+        outputs: Vec<DMatrix<N>>,
+    }
+
+    impl Controller for Model {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            Model::get_result(self, input)
+        }
+
+        fn reset_state(&self) {
+            Model::reset_state(self)
+        }
+
+        fn input_size(&self) -> Option<u32> {
+            self.layers.first().map(|l| l.input)
+        }
+
+        fn network(&self) -> Option<&Model> {
+            Some(self)
+        }
+    }
+
+    // The observation and the action stay f64, everything in between is f32.
+    impl Controller for Model<f32> {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            cast(&Model::get_result(self, &cast(input)))
+        }
+
+        fn reset_state(&self) {
+            Model::reset_state(self)
+        }
+
+        fn input_size(&self) -> Option<u32> {
+            self.layers.first().map(|l| l.input)
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,398 @@
+pub mod neat {
+    use na::DMatrix;
+    use std::collections::HashMap;
+    use crate::model::model::{Activation, Controller};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum NodeKind {
+        Input,
+        Hidden,
+        Output,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct NodeGene {
+        pub id: usize,
+        pub kind: NodeKind,
+        pub bias: f64,
+        pub activation: Activation,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct ConnectionGene {
+        pub innovation: usize,
+        pub from: usize,
+        pub to: usize,
+        pub weight: f64,
+        pub enabled: bool,
+    }
+
+    // Shared between all the genomes of a population so that the same
+    // structural mutation gets the same innovation number everywhere.
+    pub struct Innovations {
+        next_node: usize,
+        next_innovation: usize,
+        connections: HashMap<(usize, usize), usize>,
+        splits: HashMap<usize, usize>,
+    }
+
+    impl Innovations {
+        pub fn new() -> Innovations {
+            Innovations {
+                next_node: 0,
+                next_innovation: 0,
+                connections: HashMap::new(),
+                splits: HashMap::new(),
+            }
+        }
+
+        pub fn new_node(&mut self) -> usize {
+            self.next_node += 1;
+            return self.next_node - 1;
+        }
+
+        pub fn connection(&mut self, from: usize, to: usize) -> usize {
+            if let Some(i) = self.connections.get(&(from, to)) {
+                return *i;
+            }
+            let i = self.next_innovation;
+            self.next_innovation += 1;
+            self.connections.insert((from, to), i);
+            return i;
+        }
+
+        // node created when splitting the connection `innovation`
+        pub fn split(&mut self, innovation: usize) -> usize {
+            if let Some(id) = self.splits.get(&innovation) {
+                return *id;
+            }
+            let id = self.new_node();
+            self.splits.insert(innovation, id);
+            return id;
+        }
+    }
+
+    fn random_delta(delta_max: f64) -> f64 {
+        let p: f64 = rand::random();
+        let q: f64 = rand::random();
+        if p < 0.5 {
+            q * delta_max
+        } else {
+            -q * delta_max
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Genome {
+        pub nodes: Vec<NodeGene>,
+        pub connections: Vec<ConnectionGene>,
+    }
+
+    impl Genome {
+        // Every input connected to every output, like a model with a single layer.
+        pub fn new(inputs: usize, outputs: usize, activation: Activation, innovations: &mut Innovations) -> Genome {
+            let mut genome = Genome {
+                nodes: Vec::new(),
+                connections: Vec::new(),
+            };
+            for i in 0..inputs + outputs {
+                // the first genome fixes the ids of the inputs and outputs
+                let id = if i < innovations.next_node { i } else { innovations.new_node() };
+                genome.nodes.push(NodeGene {
+                    id: id,
+                    kind: if i < inputs { NodeKind::Input } else { NodeKind::Output },
+                    bias: random_delta(1.0),
+                    activation: activation.clone(),
+                });
+            }
+            for i in 0..inputs {
+                for o in inputs..inputs + outputs {
+                    genome.connections.push(ConnectionGene {
+                        innovation: innovations.connection(i, o),
+                        from: i,
+                        to: o,
+                        weight: random_delta(1.0),
+                        enabled: true,
+                    });
+                }
+            }
+            return genome;
+        }
+
+        fn node(&self, id: usize) -> Option<&NodeGene> {
+            self.nodes.iter().find(|n| n.id == id)
+        }
+
+        // same kind of mutation as Model::copy_mut
+        pub fn mutate_weights(&mut self, percent: f64, delta_max: f64) {
+            for c in self.connections.iter_mut() {
+                let p: f64 = rand::random();
+                if p < percent {
+                    c.weight += random_delta(delta_max);
+                }
+            }
+            for n in self.nodes.iter_mut() {
+                let p: f64 = rand::random();
+                if n.kind != NodeKind::Input && p < percent {
+                    n.bias += random_delta(delta_max);
+                }
+            }
+        }
+
+        // true if `to` can be reached from `from` following enabled connections
+        fn reach(&self, from: usize, to: usize) -> bool {
+            let mut stack = vec![from];
+            let mut seen = Vec::new();
+            while let Some(id) = stack.pop() {
+                if id == to {
+                    return true;
+                }
+                if seen.contains(&id) {
+                    continue;
+                }
+                seen.push(id);
+                for c in self.connections.iter() {
+                    if c.from == id && c.enabled {
+                        stack.push(c.to);
+                    }
+                }
+            }
+            return false;
+        }
+
+        // Connect two nodes that were not connected yet. The network stays
+        // feed-forward. Returns false if no connection could be added.
+        pub fn add_connection(&mut self, innovations: &mut Innovations) -> bool {
+            for _ in 0..20 {
+                let a = &self.nodes[rand::random::<usize>() % self.nodes.len()];
+                let b = &self.nodes[rand::random::<usize>() % self.nodes.len()];
+                if a.id == b.id || a.kind == NodeKind::Output || b.kind == NodeKind::Input {
+                    continue;
+                }
+                let (from, to) = (a.id, b.id);
+                if self.connections.iter().any(|c| c.from == from && c.to == to) {
+                    continue;
+                }
+                if self.reach(to, from) {
+                    continue;
+                }
+                self.connections.push(ConnectionGene {
+                    innovation: innovations.connection(from, to),
+                    from: from,
+                    to: to,
+                    weight: random_delta(1.0),
+                    enabled: true,
+                });
+                return true;
+            }
+            return false;
+        }
+
+        // Split an enabled connection in two with a new hidden node in the middle.
+        pub fn add_node(&mut self, activation: Activation, innovations: &mut Innovations) -> bool {
+            let enabled: Vec<usize> = (0..self.connections.len())
+                .filter(|i| self.connections[*i].enabled)
+                .collect();
+            if enabled.is_empty() {
+                return false;
+            }
+            let i = enabled[rand::random::<usize>() % enabled.len()];
+            let old = self.connections[i].clone();
+            let id = innovations.split(old.innovation);
+            if self.node(id).is_some() {
+                return false;
+            }
+            self.connections[i].enabled = false;
+            self.nodes.push(NodeGene {
+                id: id,
+                kind: NodeKind::Hidden,
+                bias: 0.,
+                activation: activation,
+            });
+            self.connections.push(ConnectionGene {
+                innovation: innovations.connection(old.from, id),
+                from: old.from,
+                to: id,
+                weight: 1.,
+                enabled: true,
+            });
+            self.connections.push(ConnectionGene {
+                innovation: innovations.connection(id, old.to),
+                from: id,
+                to: old.to,
+                weight: old.weight,
+                enabled: true,
+            });
+            return true;
+        }
+
+        // Matching genes are taken from either parent, disjoint and excess
+        // genes from `self` which must be the fittest parent.
+        pub fn crossover(&self, other: &Genome) -> Genome {
+            let mut child = Genome {
+                nodes: self.nodes.clone(),
+                connections: Vec::new(),
+            };
+            for c in self.connections.iter() {
+                let mut gene = c.clone();
+                if let Some(o) = other.connections.iter().find(|o| o.innovation == c.innovation) {
+                    let p: f64 = rand::random();
+                    if p < 0.5 {
+                        gene.weight = o.weight;
+                    }
+                    // a gene disabled in one parent stays disabled most of the time
+                    if !c.enabled || !o.enabled {
+                        let p: f64 = rand::random();
+                        gene.enabled = p >= 0.75;
+                    }
+                }
+                child.connections.push(gene);
+            }
+            for n in child.nodes.iter_mut() {
+                if let Some(o) = other.node(n.id) {
+                    let p: f64 = rand::random();
+                    if p < 0.5 {
+                        n.bias = o.bias;
+                    }
+                }
+            }
+            // re-enabling a gene can create a cycle
+            let mut i = 0;
+            while i < child.connections.len() {
+                let c = child.connections[i].clone();
+                if c.enabled {
+                    child.connections[i].enabled = false;
+                    if !child.reach(c.to, c.from) {
+                        child.connections[i].enabled = true;
+                    }
+                }
+                i += 1;
+            }
+            return child;
+        }
+
+        // compatibility distance: c1 * excess / n + c2 * disjoint / n + c3 * mean weight difference
+        pub fn distance(&self, other: &Genome, c1: f64, c2: f64, c3: f64) -> f64 {
+            let max_self = self.connections.iter().map(|c| c.innovation).max().unwrap_or(0);
+            let max_other = other.connections.iter().map(|c| c.innovation).max().unwrap_or(0);
+            let mut excess = 0;
+            let mut disjoint = 0;
+            let mut matching = 0;
+            let mut weight = 0.;
+            for c in self.connections.iter() {
+                match other.connections.iter().find(|o| o.innovation == c.innovation) {
+                    Some(o) => {
+                        matching += 1;
+                        weight += (c.weight - o.weight).abs();
+                    },
+                    None if c.innovation > max_other => excess += 1,
+                    None => disjoint += 1,
+                }
+            }
+            for o in other.connections.iter() {
+                if !self.connections.iter().any(|c| c.innovation == o.innovation) {
+                    if o.innovation > max_self {
+                        excess += 1;
+                    } else {
+                        disjoint += 1;
+                    }
+                }
+            }
+            let n = self.connections.len().max(other.connections.len()).max(1) as f64;
+            let w = if matching > 0 { weight / matching as f64 } else { 0. };
+            return c1 * excess as f64 / n + c2 * disjoint as f64 / n + c3 * w;
+        }
+
+        pub fn compile(&self) -> Network {
+            let mut index: HashMap<usize, usize> = HashMap::new();
+            for (i, n) in self.nodes.iter().enumerate() {
+                index.insert(n.id, i);
+            }
+            // topological order of the nodes (Kahn)
+            let mut degree = vec![0; self.nodes.len()];
+            for c in self.connections.iter().filter(|c| c.enabled) {
+                degree[index[&c.to]] += 1;
+            }
+            let mut ready: Vec<usize> = (0..self.nodes.len()).filter(|i| degree[*i] == 0).collect();
+            let mut order = Vec::new();
+            while let Some(i) = ready.pop() {
+                order.push(i);
+                for c in self.connections.iter().filter(|c| c.enabled && index[&c.from] == i) {
+                    let j = index[&c.to];
+                    degree[j] -= 1;
+                    if degree[j] == 0 {
+                        ready.push(j);
+                    }
+                }
+            }
+
+            let mut steps = Vec::new();
+            for i in order {
+                let n = &self.nodes[i];
+                if n.kind == NodeKind::Input {
+                    continue;
+                }
+                let mut incoming = Vec::new();
+                for c in self.connections.iter().filter(|c| c.enabled && c.to == n.id) {
+                    incoming.push((index[&c.from], c.weight));
+                }
+                steps.push(NetworkNode {
+                    node: i,
+                    bias: n.bias,
+                    activation: n.activation.clone(),
+                    incoming: incoming,
+                });
+            }
+            Network {
+                size: self.nodes.len(),
+                inputs: (0..self.nodes.len()).filter(|i| self.nodes[*i].kind == NodeKind::Input).collect(),
+                outputs: (0..self.nodes.len()).filter(|i| self.nodes[*i].kind == NodeKind::Output).collect(),
+                steps: steps,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct NetworkNode {
+        node: usize,
+        bias: f64,
+        activation: Activation,
+        incoming: Vec<(usize, f64)>,
+    }
+
+    // Runnable form of a genome, nodes are evaluated in topological order.
+    #[derive(Debug, Clone)]
+    pub struct Network {
+        size: usize,
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+        steps: Vec<NetworkNode>,
+    }
+
+    impl Network {
+        pub fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            let mut values = vec![0.; self.size];
+            for (i, n) in self.inputs.iter().enumerate() {
+                values[*n] = input[i];
+            }
+            for step in self.steps.iter() {
+                let mut sum = step.bias;
+                for (from, w) in step.incoming.iter() {
+                    sum += values[*from] * w;
+                }
+                values[step.node] = step.activation.apply(sum);
+            }
+            let mut result = DMatrix::zeros(self.outputs.len(), 1);
+            for (i, n) in self.outputs.iter().enumerate() {
+                result[i] = values[*n];
+            }
+            return result;
+        }
+    }
+
+    impl Controller for Network {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            Network::get_result(self, input)
+        }
+    }
+}
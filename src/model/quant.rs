@@ -0,0 +1,302 @@
+pub mod quant {
+    use na::DMatrix;
+    use crate::Map;
+    use crate::model::model::{is_registered, Activation, Controller, Kind, Model, ModelError};
+    use crate::train::pool::pool;
+    use std::fs;
+    use std::io::{self, Error, ErrorKind};
+    use std::path::Path;
+
+    // Affine int8 quantization: x = (q - zero) * scale.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct QParams {
+        pub scale: f32,
+        pub zero: i32,
+    }
+
+    impl QParams {
+        // the 256 values of an i8 spread over [min, max], 0 is always exact
+        pub fn from_range(min: f64, max: f64) -> QParams {
+            let (min, max) = (min.min(0.), max.max(0.));
+            let scale = if max > min { (max - min) / 255. } else { 1. };
+            let zero = (-128. - min / scale).round().max(-128.).min(127.);
+            QParams {
+                scale: scale as f32,
+                zero: zero as i32,
+            }
+        }
+
+        pub fn quantize(&self, x: f64) -> i8 {
+            ((x / self.scale as f64).round() + self.zero as f64).max(-128.).min(127.) as i8
+        }
+    }
+
+    // Dense layer with int8 weights. The inputs are quantized with `input`,
+    // the products are summed in i32 with the bias, then scaled back to
+    // float for the activation.
+    #[derive(Debug, Clone)]
+    pub struct QuantizedLayer {
+        pub input: u32,
+        pub neuron: u32,
+        pub activation: Activation,
+        // neuron rows of input values
+        pub weights: Vec<i8>,
+        pub weight: QParams,
+        // in units of weight.scale * input.scale
+        pub bias: Vec<i32>,
+        pub input_params: QParams,
+    }
+
+    impl QuantizedLayer {
+        pub fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            let (n, m) = (self.neuron as usize, self.input as usize);
+            let scale = self.weight.scale as f64 * self.input_params.scale as f64;
+            let mut output = DMatrix::zeros(n, input.ncols());
+            let mut x = vec![0i32; m];
+            for j in 0..input.ncols() {
+                for k in 0..m {
+                    x[k] = self.input_params.quantize(input[(k, j)]) as i32 - self.input_params.zero;
+                }
+                for i in 0..n {
+                    let mut acc: i32 = self.bias[i];
+                    for k in 0..m {
+                        acc += (self.weights[i * m + k] as i32 - self.weight.zero) * x[k];
+                    }
+                    output[(i, j)] = acc as f64 * scale;
+                }
+            }
+            self.activation.compute_mut(&mut output);
+            return output;
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct QuantizedModel {
+        pub layers: Vec<QuantizedLayer>,
+    }
+
+    impl QuantizedModel {
+        pub fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            let mut temp = input.clone();
+            for a in self.layers.iter() {
+                temp = a.get_result(&temp);
+            }
+            return temp;
+        }
+
+        pub fn size_in_bytes(&self) -> usize {
+            self.layers.iter().map(|a| a.weights.len() + 4 * a.bias.len() + 16).sum()
+        }
+    }
+
+    impl Controller for QuantizedModel {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            QuantizedModel::get_result(self, input)
+        }
+
+        fn input_size(&self) -> Option<u32> {
+            self.layers.first().map(|a| a.input)
+        }
+    }
+
+    fn range(m: &DMatrix<f64>) -> (f64, f64) {
+        (m.iter().cloned().fold(0., f64::min), m.iter().cloned().fold(0., f64::max))
+    }
+
+    // Post-training quantization. `calibration` holds typical inputs, one per
+    // column: the range of the input of every layer is measured on them.
+    pub fn quantize(model: &Model, calibration: &DMatrix<f64>) -> Result<QuantizedModel, ModelError> {
+        if model.layers().is_empty() {
+            return Err(ModelError::Empty);
+        }
+        let mut layers = Vec::new();
+        let mut input = calibration.clone();
+        for (i, a) in model.layers().iter().enumerate() {
+            if a.kind() != Kind::Dense {
+                return Err(ModelError::NotDense { layer: i, kind: a.kind().name() });
+            }
+            let params = a.params();
+            let (w, b) = (params[0].1, params[1].1);
+            let (min, max) = range(w);
+            let weight = QParams::from_range(min, max);
+            let (min, max) = range(&input);
+            let input_params = QParams::from_range(min, max);
+            let scale = weight.scale as f64 * input_params.scale as f64;
+            // w is stored column by column, the engine reads it row by row
+            let weights = w.transpose().iter().map(|v| weight.quantize(*v)).collect();
+            let bias = b.iter().map(|v| (v / scale).round().max(i32::min_value() as f64).min(i32::max_value() as f64) as i32).collect();
+            layers.push(QuantizedLayer {
+                input: a.input(),
+                neuron: a.neuron(),
+                activation: a.activation().clone(),
+                weights: weights,
+                weight: weight,
+                bias: bias,
+                input_params: input_params,
+            });
+            input = a.get_result(&input);
+        }
+        return Ok(QuantizedModel { layers: layers });
+    }
+
+    pub struct Report {
+        // absolute difference between the float and the quantized outputs
+        pub max_error: f64,
+        pub mean_error: f64,
+        pub float_d: usize,
+        pub quantized_d: usize,
+        pub float_bytes: usize,
+        pub quantized_bytes: usize,
+    }
+
+    // Compare the outputs on `inputs` (one per column) and the distance driven on the map.
+    pub fn report(map: &Map, model: &Model, quantized: &QuantizedModel, inputs: &DMatrix<f64>) -> Report {
+        let error = (model.get_result(inputs) - quantized.get_result(inputs)).abs();
+        Report {
+            max_error: error.max(),
+            mean_error: error.mean(),
+            float_d: pool::distance(map, model),
+            quantized_d: pool::run_episode(map, quantized).d as usize,
+            float_bytes: 8 * model.num_parameters(),
+            quantized_bytes: quantized.size_in_bytes(),
+        }
+    }
+
+    // Text format, for every layer a header line
+    //     int8 <in> <neuron> <activation> <weight scale> <weight zero> <input scale> <input zero>
+    // then a line with the weights, row by row, and a line with the biases.
+    pub fn to_string(model: &QuantizedModel) -> String {
+        let mut s = String::new();
+        for a in model.layers.iter() {
+            s.push_str(&format!("int8 {} {} {} {} {} {} {}\n", a.input, a.neuron, a.activation.name(),
+                                a.weight.scale, a.weight.zero, a.input_params.scale, a.input_params.zero));
+            let weights: Vec<String> = a.weights.iter().map(|v| v.to_string()).collect();
+            s.push_str(&weights.join(" "));
+            s.push('\n');
+            let bias: Vec<String> = a.bias.iter().map(|v| v.to_string()).collect();
+            s.push_str(&bias.join(" "));
+            s.push('\n');
+        }
+        return s;
+    }
+
+    fn invalid(message: String) -> Error {
+        Error::new(ErrorKind::InvalidData, message)
+    }
+
+    fn parse<T: std::str::FromStr>(word: &str) -> io::Result<T> {
+        word.parse().map_err(|_| invalid(format!("bad value: {}", word)))
+    }
+
+    pub fn from_str(s: &str) -> io::Result<QuantizedModel> {
+        let lines: Vec<&str> = s.lines().collect();
+        if lines.len() % 3 != 0 || lines.is_empty() {
+            return Err(invalid("a layer takes three lines".to_string()));
+        }
+        let mut layers: Vec<QuantizedLayer> = Vec::new();
+        for chunk in lines.chunks(3) {
+            let words: Vec<&str> = chunk[0].split_whitespace().collect();
+            if words.len() != 8 || words[0] != "int8" {
+                return Err(invalid(format!("bad layer: {}", chunk[0])));
+            }
+            let input: u32 = parse(words[1])?;
+            let neuron: u32 = parse(words[2])?;
+            let activation = Activation::from_name(words[3])
+                .ok_or_else(|| invalid(format!("unknown activation: {}", words[3])))?;
+            if let Activation::Custom(ref name) = activation {
+                if !is_registered(name) {
+                    return Err(invalid(format!("activation {} is not registered", name)));
+                }
+            }
+            let weights = chunk[1].split_whitespace().map(parse).collect::<io::Result<Vec<i8>>>()?;
+            let bias = chunk[2].split_whitespace().map(parse).collect::<io::Result<Vec<i32>>>()?;
+            if weights.len() != (input * neuron) as usize || bias.len() != neuron as usize {
+                return Err(invalid(format!("wrong number of parameters for {}", chunk[0])));
+            }
+            if let Some(last) = layers.last() {
+                if last.neuron != input {
+                    return Err(invalid(format!("layer {} takes {} inputs instead of {}", layers.len(), input, last.neuron)));
+                }
+            }
+            layers.push(QuantizedLayer {
+                input: input,
+                neuron: neuron,
+                activation: activation,
+                weights: weights,
+                weight: QParams { scale: parse(words[4])?, zero: parse(words[5])? },
+                bias: bias,
+                input_params: QParams { scale: parse(words[6])?, zero: parse(words[7])? },
+            });
+        }
+        return Ok(QuantizedModel { layers: layers });
+    }
+
+    pub fn save<P: AsRef<Path>>(model: &QuantizedModel, path: P) -> io::Result<()> {
+        fs::write(path, to_string(model))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<QuantizedModel> {
+        from_str(&fs::read_to_string(path)?)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::{Init, Layer};
+        use rand::prelude::*;
+        use rand::rngs::StdRng;
+
+        fn model() -> Model {
+            let mut model = Model::new();
+            model.add_layer(Layer::new_init(6, 8, Activation::Tanh, Init::XavierUniform, 5)).unwrap();
+            model.add_layer(Layer::new_init(8, 2, Activation::Tanh, Init::XavierUniform, 6)).unwrap();
+            return model;
+        }
+
+        fn inputs(n: usize) -> DMatrix<f64> {
+            let mut rng = StdRng::seed_from_u64(11);
+            DMatrix::from_fn(6, n, |_, _| rng.gen_range(0., 1.))
+        }
+
+        #[test]
+        fn round_trip() {
+            let quantized = quantize(&model(), &inputs(100)).unwrap();
+            let loaded = from_str(&to_string(&quantized)).unwrap();
+            let input = inputs(10);
+            assert_eq!(quantized.get_result(&input), loaded.get_result(&input));
+            assert_eq!(to_string(&quantized), to_string(&loaded));
+        }
+
+        #[test]
+        fn bad_parameter_count() {
+            let s = to_string(&quantize(&model(), &inputs(100)).unwrap());
+            let lines: Vec<String> = s.lines().map(|l| l.to_string()).collect();
+            // one weight too many
+            let mut more = lines.clone();
+            more[1].push_str(" 3");
+            assert_eq!(from_str(&more.join("\n")).unwrap_err().kind(), ErrorKind::InvalidData);
+            // one bias missing
+            let mut less = lines.clone();
+            less[2] = less[2].splitn(2, ' ').nth(1).unwrap().to_string();
+            assert_eq!(from_str(&less.join("\n")).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert!(from_str(&lines.join("\n")).is_ok());
+        }
+
+        #[test]
+        fn mismatched_layers() {
+            let s = "int8 2 3 tanh 0.01 0 0.01 0\n1 2 3 4 5 6\n0 0 0\nint8 2 1 tanh 0.01 0 0.01 0\n1 2\n0\n";
+            assert_eq!(from_str(s).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert!(from_str(&s.replace("int8 2 1", "int8 3 1").replace("\n1 2\n", "\n1 2 3\n")).is_ok());
+        }
+
+        #[test]
+        fn close_to_the_float_model() {
+            let model = model();
+            let quantized = quantize(&model, &inputs(200)).unwrap();
+            let input = inputs(50);
+            let error = (model.get_result(&input) - quantized.get_result(&input)).abs().max();
+            // about 0.011 with these seeds, a few steps of 1/255
+            assert!(error < 0.02, "max error {}", error);
+        }
+    }
+}
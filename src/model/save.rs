@@ -0,0 +1,148 @@
+pub mod save {
+    use na::DVector;
+    use crate::model::model::{is_registered, Activation, Init, Kind, Layer, Model};
+    use std::fs;
+    use std::io::{self, Error, ErrorKind};
+    use std::path::Path;
+
+    // Text format: one line per layer with its shape, activation and how
+    // it was initialized, then one line with all the parameters of the model
+    // and, for a pruned model, the indices of the pruned parameters.
+    //
+    //     dense 58 20 sigmoid xavier_uniform 1234
+    //     gru 20 8 tanh xavier_uniform 42
+    //     dense 8 2 tanh uniform 98765
+    //     0.12 -0.5 ...
+    //     pruned 3 17 ...
+    pub fn to_string(model: &Model) -> String {
+        let mut s = String::new();
+        for l in model.layers().iter() {
+            let (init, seed) = l.init();
+            s.push_str(&format!("{} {} {} {} {} {}\n", l.kind().name(), l.input(), l.neuron(), l.activation().name(),
+                                init.name(), seed));
+        }
+        let parameters: Vec<String> = model.parameters().iter().map(|p| p.to_string()).collect();
+        s.push_str(&parameters.join(" "));
+        s.push('\n');
+        let pruned = model.pruned();
+        if !pruned.is_empty() {
+            let pruned: Vec<String> = pruned.iter().map(|i| i.to_string()).collect();
+            s.push_str(&format!("pruned {}\n", pruned.join(" ")));
+        }
+        return s;
+    }
+
+    fn invalid(message: String) -> Error {
+        Error::new(ErrorKind::InvalidData, message)
+    }
+
+    pub fn from_str(s: &str) -> io::Result<Model> {
+        let mut model = Model::new();
+        let mut lines: Vec<&str> = s.lines().filter(|l| !l.trim().is_empty()).collect();
+        let pruned = match lines.last() {
+            Some(l) if l.starts_with("pruned") => lines.pop(),
+            _ => None,
+        };
+        if lines.is_empty() {
+            return Err(invalid("empty model".to_string()));
+        }
+        for line in lines[..lines.len() - 1].iter() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            // older files have no init
+            if words.len() != 4 && words.len() != 6 {
+                return Err(invalid(format!("bad layer: {}", line)));
+            }
+            let kind = Kind::from_name(words[0])
+                .ok_or_else(|| invalid(format!("unknown layer kind: {}", words[0])))?;
+            let input = words[1].parse().map_err(|_| invalid(format!("bad input size: {}", line)))?;
+            let neuron = words[2].parse().map_err(|_| invalid(format!("bad neuron count: {}", line)))?;
+            let activation = Activation::from_name(words[3])
+                .ok_or_else(|| invalid(format!("unknown activation: {}", words[3])))?;
+            // fail here rather than in the first evaluation
+            if let Activation::Custom(ref name) = activation {
+                if !is_registered(name) {
+                    return Err(invalid(format!("activation {} is not registered", name)));
+                }
+            }
+            // Layer::new_kind asserts on the geometry of a convolution
+            if let Kind::Conv(c) = kind {
+                if !c.is_valid() || c.input_size() != input || c.output_size() != neuron {
+                    return Err(invalid(format!("bad convolution: {}", line)));
+                }
+            }
+            if words.len() == 6 {
+                let init = Init::from_name(words[4])
+                    .ok_or_else(|| invalid(format!("unknown init: {}", words[4])))?;
+                let seed = words[5].parse().map_err(|_| invalid(format!("bad seed: {}", line)))?;
+                model.add_layer(Layer::new_kind(kind, input, neuron, activation, init, seed))
+                    .map_err(|e| invalid(e.to_string()))?;
+            } else {
+                model.add_layer(Layer::new_kind(kind, input, neuron, activation, Init::Zeros, 0))
+                    .map_err(|e| invalid(e.to_string()))?;
+            }
+        }
+        let mut parameters = Vec::new();
+        for p in lines[lines.len() - 1].split_whitespace() {
+            parameters.push(p.parse::<f64>().map_err(|_| invalid(format!("bad parameter: {}", p)))?);
+        }
+        if parameters.len() != model.num_parameters() {
+            return Err(invalid(format!("{} parameters for a model of {}", parameters.len(), model.num_parameters())));
+        }
+        model.set_parameters(&DVector::from_vec(parameters));
+        if let Some(line) = pruned {
+            for i in line.split_whitespace().skip(1) {
+                let i: usize = i.parse().map_err(|_| invalid(format!("bad pruned parameter: {}", i)))?;
+                if i >= model.num_parameters() {
+                    return Err(invalid(format!("pruned parameter {} out of a model of {}", i, model.num_parameters())));
+                }
+                model.prune(i);
+            }
+        }
+        return Ok(model);
+    }
+
+    pub fn save<P: AsRef<Path>>(model: &Model, path: P) -> io::Result<()> {
+        fs::write(path, to_string(model))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Model> {
+        from_str(&fs::read_to_string(path)?)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::register_activation;
+        use na::DMatrix;
+
+        #[test]
+        fn custom_activation_round_trip() {
+            register_activation("test_cube", |x: f64| x * x * x, Some(|x: f64| 3. * x * x));
+            let mut model = Model::new();
+            model.add_layer(Layer::new_init(3, 4, Activation::Custom("test_cube".to_string()), Init::XavierUniform, 1)).unwrap();
+            model.add_layer(Layer::new_init(4, 2, Activation::Tanh, Init::XavierUniform, 2)).unwrap();
+            let loaded = from_str(&to_string(&model)).unwrap();
+            let input = DMatrix::from_column_slice(3, 1, &[0.5, -1., 2.]);
+            assert_eq!(model.get_result(&input), loaded.get_result(&input));
+        }
+
+        #[test]
+        fn unregistered_activation() {
+            let s = "dense 2 1 custom(test_missing) zeros 0\n0 0 0\n";
+            assert!(from_str(s).is_err());
+            assert!(from_str(&s.replace("custom(test_missing)", "tanh")).is_ok());
+        }
+
+        #[test]
+        fn bad_convolution() {
+            let parameters = vec!["0"; 40].join(" ");
+            // 4 filters of 3 x 3 on the 8 x 7 radar pooled by 2 give 24 outputs
+            let s = format!("conv(1,8,7,4,3,1,2) 56 20 relu he 1\n{}\n", parameters);
+            assert_eq!(from_str(&s).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert_eq!(from_str(&s.replace("56 20", "50 24")).unwrap_err().kind(), ErrorKind::InvalidData);
+            // a kernel larger than the radar
+            assert_eq!(from_str(&s.replace("4,3,1", "4,9,1")).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert!(from_str(&s.replace("56 20", "56 24")).is_ok());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+pub mod cloning {
+    use crate::Map;
+    use crate::Model;
+    use crate::Car;
+    use crate::Controller;
+    use crate::model::grad::grad::{Dataset, Loss, Optimizer};
+    use crate::train::pool::pool;
+    use na::DVector;
+    use rand::distributions::StandardNormal;
+    use rand::prelude::*;
+
+    // Drive the teacher and record what it sees and what it answers. With
+    // `noise` > 0 the car does not follow exactly the teacher, so the
+    // dataset also shows how to recover from small mistakes.
+    pub fn collect<C: Controller>(map: &Map, teacher: &C, episodes: usize, noise: f64) -> Dataset {
+        let mut rng = thread_rng();
+        let mut dataset = Dataset::new();
+        for _ in 0..episodes {
+            let mut car = Car::new(500.0, 500., 0.0);
+            let mut n = 0;
+            teacher.reset_state();
+            loop {
+                let observation = car.observe(teacher);
+                let action = teacher.get_result(&observation);
+                dataset.push(DVector::from_column_slice(observation.as_slice()),
+                             DVector::from_column_slice(action.as_slice()));
+                let throttle = action[0] + noise * rng.sample::<f64, _>(StandardNormal);
+                let steering = action[1] + noise * rng.sample::<f64, _>(StandardNormal);
+                car.set_action(throttle, steering);
+                car.update(map);
+                if pool::episode_over(&car, n) {
+                    break;
+                }
+                n = n + 1;
+            }
+        }
+        return dataset;
+    }
+
+    pub struct Report {
+        pub loss: f64,
+        // part of the samples where the student throttles and steers in the same direction
+        pub agreement: f64,
+        pub student_d: usize,
+        pub teacher_d: Option<usize>,
+    }
+
+    pub fn agreement(student: &Model, dataset: &Dataset) -> f64 {
+        let mut same = 0;
+        student.reset_state();
+        for (input, target) in dataset.samples.iter() {
+            let input = na::DMatrix::from_column_slice(input.len(), 1, input.as_slice());
+            let output = student.get_result(&input);
+            if (output[0] >= 0.) == (target[0] >= 0.) && (output[1] >= 0.) == (target[1] >= 0.) {
+                same += 1;
+            }
+        }
+        return same as f64 / dataset.len().max(1) as f64;
+    }
+
+    pub fn report(map: &Map, student: &Model, dataset: &Dataset, teacher: Option<&Model>) -> Report {
+        Report {
+            loss: student.loss(dataset, Loss::Mse),
+            agreement: agreement(student, dataset),
+            student_d: pool::distance(map, student),
+            teacher_d: teacher.map(|t| pool::distance(map, t)),
+        }
+    }
+
+    // Train the student on the dataset, printing the report every few epochs.
+    pub fn train(map: &Map, student: Model, dataset: &Dataset, teacher: Option<&Model>,
+                 epochs: usize, optimizer: &mut Optimizer) -> Model {
+        let mut student = student;
+        let mut best = (student.clone(), 0);
+        for epoch in 0..epochs / 10 {
+            let history = student.fit_with(dataset, 10, 32, Loss::Mse, optimizer);
+            let r = report(map, &student, dataset, teacher);
+            println!("epoch {}: train loss={:.5}, loss={:.5}, agreement={:.1}%, student d={}, teacher d={:?}",
+                     (epoch + 1) * 10, history.last().unwrap(), r.loss, r.agreement * 100.,
+                     r.student_d, r.teacher_d);
+            if r.student_d >= best.1 {
+                best = (student.clone(), r.student_d);
+            }
+        }
+        return best.0;
+    }
+}
@@ -0,0 +1,34 @@
+pub mod curve {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    // Learning curve of a training: after how many episodes, the mean and
+    // the best distance driven. Saved as csv to compare the trainings.
+    pub struct Curve {
+        pub episodes: usize,
+        pub points: Vec<(usize, f64, f64)>,
+    }
+
+    impl Curve {
+        pub fn new() -> Curve {
+            Curve {
+                episodes: 0,
+                points: Vec::new(),
+            }
+        }
+
+        pub fn push(&mut self, episodes: usize, mean: f64, best: f64) {
+            self.episodes += episodes;
+            self.points.push((self.episodes, mean, best));
+        }
+
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let mut s = String::from("episodes,mean,best\n");
+            for p in self.points.iter() {
+                s.push_str(&format!("{},{},{}\n", p.0, p.1, p.2));
+            }
+            fs::write(path, s)
+        }
+    }
+}
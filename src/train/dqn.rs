@@ -0,0 +1,267 @@
+pub mod dqn {
+    use crate::Map;
+    use crate::Model;
+    use crate::Car;
+    use crate::Controller;
+    use crate::model::grad::grad::Optimizer;
+    use crate::model::save::save;
+    use crate::train::curve::curve::Curve;
+    use crate::train::pool::pool;
+    use na::{DMatrix, DVector};
+    use rand::prelude::*;
+    use std::fs;
+    use std::io::{self, Error, ErrorKind};
+    use std::path::Path;
+
+    // accelerate, coast or brake, combined with left, straight or right
+    pub const THROTTLES: [f64; 3] = [0.2, 0., -0.2];
+    pub const STEERINGS: [f64; 3] = [1., 0., -1.];
+    pub const ACTIONS: usize = 9;
+
+    // [throttle, steering] of a discrete action
+    pub fn action(i: usize) -> (f64, f64) {
+        (THROTTLES[i / 3], STEERINGS[i % 3])
+    }
+
+    fn argmax(values: &DMatrix<f64>, column: usize) -> usize {
+        let mut best = 0;
+        for i in 0..values.nrows() {
+            if values[(i, column)] > values[(best, column)] {
+                best = i;
+            }
+        }
+        return best;
+    }
+
+    // Greedy policy of a Q network, drives like any other model.
+    #[derive(Debug, Clone)]
+    pub struct DiscretePolicy {
+        pub q: Model,
+    }
+
+    impl Controller for DiscretePolicy {
+        fn get_result(&self, input: &DMatrix<f64>) -> DMatrix<f64> {
+            let (throttle, steering) = action(argmax(&self.q.get_result(input), 0));
+            DMatrix::from_column_slice(2, 1, &[throttle, steering])
+        }
+
+        fn reset_state(&self) {
+            self.q.reset_state();
+        }
+
+        fn input_size(&self) -> Option<u32> {
+            self.q.input_size()
+        }
+
+        fn network(&self) -> Option<&Model> {
+            Some(&self.q)
+        }
+    }
+
+    // The Q network in the model format after a `discrete` line, so that
+    // it is not mistaken for a model that outputs throttle and steering.
+    pub fn save<P: AsRef<Path>>(policy: &DiscretePolicy, path: P) -> io::Result<()> {
+        fs::write(path, format!("discrete\n{}", save::to_string(&policy.q)))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<DiscretePolicy> {
+        let s = fs::read_to_string(path)?;
+        let mut parts = s.splitn(2, '\n');
+        if parts.next().map(|l| l.trim()) != Some("discrete") {
+            return Err(Error::new(ErrorKind::InvalidData, "not a discrete policy"));
+        }
+        let q = save::from_str(parts.next().unwrap_or(""))?;
+        if q.layers().last().map(|l| l.neuron() as usize) != Some(ACTIONS) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("the q network must have {} outputs", ACTIONS)));
+        }
+        return Ok(DiscretePolicy { q: q });
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Schedule {
+        Constant(f64),
+        // from start to end in that many steps
+        Linear(f64, f64, usize),
+        // start, end, decay: multiplied by decay at every step until end
+        Exponential(f64, f64, f64),
+    }
+
+    impl Schedule {
+        pub fn epsilon(&self, step: usize) -> f64 {
+            match *self {
+                Schedule::Constant(e) => e,
+                Schedule::Linear(start, end, steps) => {
+                    let t = (step as f64 / steps.max(1) as f64).min(1.);
+                    start + (end - start) * t
+                },
+                Schedule::Exponential(start, end, decay) => {
+                    (start * decay.powi(step as i32)).max(end)
+                },
+            }
+        }
+    }
+
+    struct Transition {
+        observation: DVector<f64>,
+        action: usize,
+        reward: f64,
+        next: DVector<f64>,
+        done: bool,
+    }
+
+    // Last `capacity` transitions, the oldest are overwritten.
+    pub struct ReplayBuffer {
+        capacity: usize,
+        next: usize,
+        transitions: Vec<Transition>,
+    }
+
+    impl ReplayBuffer {
+        pub fn new(capacity: usize) -> ReplayBuffer {
+            ReplayBuffer {
+                capacity: capacity,
+                next: 0,
+                transitions: Vec::new(),
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.transitions.len()
+        }
+
+        fn push(&mut self, transition: Transition) {
+            if self.transitions.len() < self.capacity {
+                self.transitions.push(transition);
+            } else {
+                self.transitions[self.next] = transition;
+            }
+            self.next = (self.next + 1) % self.capacity;
+        }
+
+        fn sample(&self, size: usize, rng: &mut ThreadRng) -> Vec<&Transition> {
+            let mut batch = Vec::new();
+            for _ in 0..size {
+                batch.push(&self.transitions[rng.gen_range(0, self.transitions.len())]);
+            }
+            return batch;
+        }
+    }
+
+    pub struct Dqn {
+        pub gamma: f64,
+        pub learning_rate: f64,
+        pub batch_size: usize,
+        pub buffer_size: usize,
+        // steps between two copies of the network into the target network
+        pub target_update: usize,
+        pub epsilon: Schedule,
+        pub crash_penalty: f64,
+        // rewards are scaled so that the Q values stay in the range of tanh
+        pub reward_scale: f64,
+    }
+
+    impl Dqn {
+        pub fn new() -> Dqn {
+            Dqn {
+                gamma: 0.95,
+                learning_rate: 0.0005,
+                batch_size: 32,
+                buffer_size: 50000,
+                target_update: 500,
+                epsilon: Schedule::Linear(1.0, 0.05, 50000),
+                crash_penalty: 50.,
+                reward_scale: 0.01,
+            }
+        }
+
+        // One gradient step on the squared TD error of the actions taken.
+        fn learn(&self, q: &mut Model, target: &Model, batch: &Vec<&Transition>, optimizer: &mut Optimizer) {
+            let observations: Vec<DVector<f64>> = batch.iter().map(|t| t.observation.clone()).collect();
+            let nexts: Vec<DVector<f64>> = batch.iter().map(|t| t.next.clone()).collect();
+            let observations = DMatrix::from_columns(&observations);
+            let next_values = target.forward_trace(&DMatrix::from_columns(&nexts)).last().unwrap().1.clone();
+
+            let trace = q.forward_trace(&observations);
+            let values = &trace.last().unwrap().1;
+            let mut grad_output = DMatrix::zeros(ACTIONS, batch.len());
+            for (i, t) in batch.iter().enumerate() {
+                let mut y = t.reward;
+                if !t.done {
+                    y += self.gamma * next_values[(argmax(&next_values, i), i)];
+                }
+                grad_output[(t.action, i)] = 2. * (values[(t.action, i)] - y) / batch.len() as f64;
+            }
+            let gradient = q.backward(&observations, &trace, grad_output);
+            let mut parameters = q.parameters();
+            optimizer.step(&mut parameters, &gradient);
+            q.set_parameters(&parameters);
+        }
+
+        // `q` must have 58 inputs and one output per action.
+        pub fn train(&self, map: &Map, q: Model, episodes: usize, curve: &mut Curve) -> DiscretePolicy {
+            let mut rng = thread_rng();
+            let mut q = q;
+            let mut target = q.clone();
+            let mut optimizer = Optimizer::adam(self.learning_rate);
+            let mut buffer = ReplayBuffer::new(self.buffer_size);
+            let mut best = (q.clone(), 0);
+            let mut step = 0;
+
+            for episode in 0..episodes {
+                let mut car = Car::new(500.0, 500., 0.0);
+                let mut n = 0;
+                q.reset_state();
+                loop {
+                    let observation = car.observe(&q);
+                    let a = if rng.gen::<f64>() < self.epsilon.epsilon(step) {
+                        rng.gen_range(0, ACTIONS)
+                    } else {
+                        argmax(&q.get_result(&observation), 0)
+                    };
+                    let (throttle, steering) = action(a);
+                    car.set_action(throttle, steering);
+                    let d = car.d;
+                    car.update(map);
+                    let mut reward = (car.d - d) as f64;
+                    if !car.alive {
+                        reward -= self.crash_penalty;
+                    }
+                    // a car stopped by the time limit is not a terminal state
+                    let done = pool::stopped(&car, n);
+                    buffer.push(Transition {
+                        observation: DVector::from_column_slice(observation.as_slice()),
+                        action: a,
+                        reward: reward * self.reward_scale,
+                        next: DVector::from_column_slice(car.observe(&q).as_slice()),
+                        done: done,
+                    });
+
+                    if buffer.len() >= self.batch_size {
+                        let batch = buffer.sample(self.batch_size, &mut rng);
+                        self.learn(&mut q, &target, &batch, &mut optimizer);
+                    }
+                    step += 1;
+                    if step % self.target_update == 0 {
+                        target = q.clone();
+                    }
+                    if pool::episode_over(&car, n) {
+                        break;
+                    }
+                    n = n + 1;
+                }
+
+                curve.push(1, car.d as f64, car.d as f64);
+                if episode % 10 == 0 {
+                    let policy = DiscretePolicy { q: q.clone() };
+                    let greedy = pool::run_episode(map, &policy).d as usize;
+                    if greedy >= best.1 {
+                        best = (q.clone(), greedy);
+                    }
+                    println!("dqn episode {}: d={}, greedy d={}, epsilon={:.3}, buffer={}",
+                             episode, car.d, greedy, self.epsilon.epsilon(step), buffer.len());
+                }
+            }
+            return DiscretePolicy { q: best.0 };
+        }
+    }
+}
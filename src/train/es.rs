@@ -0,0 +1,178 @@
+pub mod es {
+    use crate::Map;
+    use crate::Model;
+    use crate::train::pool::pool::{self, Pool};
+    use na::{DMatrix, DVector};
+    use rand::distributions::StandardNormal;
+    use rand::prelude::*;
+    use std::sync::Arc;
+
+    fn gaussian(n: usize, rng: &mut ThreadRng) -> DVector<f64> {
+        DVector::from_fn(n, |_, _| rng.sample(StandardNormal))
+    }
+
+    // Fitness replaced by its rank, scaled to [-0.5, 0.5], so a few very
+    // good or very bad cars do not dominate the update.
+    pub fn centered_ranks(fitness: &Vec<usize>) -> Vec<f64> {
+        let mut order: Vec<usize> = (0..fitness.len()).collect();
+        order.sort_by(|a, b| fitness[*a].cmp(&fitness[*b]));
+        let mut ranks = vec![0.; fitness.len()];
+        let n = (fitness.len().max(2) - 1) as f64;
+        for (rank, i) in order.iter().enumerate() {
+            ranks[*i] = rank as f64 / n - 0.5;
+        }
+        return ranks;
+    }
+
+    fn with_parameters(model: &Model, parameters: &DVector<f64>) -> Model {
+        let mut m = model.clone();
+        m.set_parameters(parameters);
+        return m;
+    }
+
+    pub struct OpenAiEs {
+        pub population: usize,
+        pub sigma: f64,
+        pub learning_rate: f64,
+        pub weight_decay: f64,
+    }
+
+    impl OpenAiEs {
+        pub fn new() -> OpenAiEs {
+            OpenAiEs {
+                population: 200,
+                sigma: 0.1,
+                learning_rate: 0.03,
+                weight_decay: 0.005,
+            }
+        }
+
+        // Antithetic sampling: every noise is evaluated as theta + sigma * eps
+        // and theta - sigma * eps.
+        pub fn train(&self, map: &Map, model: Model, iterations: usize) -> Model {
+            let pool = Pool::new_batched(Arc::new(map.clone()), pool::distances);
+            let mut rng = thread_rng();
+            let mut theta = model.parameters();
+            let n = theta.len();
+            let pairs = self.population / 2;
+            for iteration in 0..iterations {
+                let mut noises = Vec::new();
+                let mut models = Vec::new();
+                for _ in 0..pairs {
+                    let eps = gaussian(n, &mut rng);
+                    models.push(with_parameters(&model, &(&theta + &eps * self.sigma)));
+                    models.push(with_parameters(&model, &(&theta - &eps * self.sigma)));
+                    noises.push(eps);
+                }
+                let evaluated = pool.evaluate(models);
+                let fitness: Vec<usize> = evaluated.iter().map(|e| e.1).collect();
+                let ranks = centered_ranks(&fitness);
+
+                let mut gradient = DVector::zeros(n);
+                for (i, eps) in noises.iter().enumerate() {
+                    gradient += eps * (ranks[2 * i] - ranks[2 * i + 1]);
+                }
+                gradient /= 2. * pairs as f64 * self.sigma;
+                theta = &theta * (1. - self.weight_decay) + gradient * self.learning_rate;
+
+                let center = pool::distance(map, &with_parameters(&model, &theta));
+                println!("es iteration {}: best d={}, center d={}", iteration,
+                         fitness.iter().max().unwrap(), center);
+            }
+            return with_parameters(&model, &theta);
+        }
+    }
+
+    // CMA-ES keeps a full covariance matrix of the parameters: only usable
+    // for small networks.
+    pub struct CmaEs {
+        pub sigma: f64,
+        pub population: usize,
+    }
+
+    impl CmaEs {
+        pub fn new(sigma: f64, parameters: usize) -> CmaEs {
+            CmaEs {
+                sigma: sigma,
+                population: 4 + (3. * (parameters as f64).ln()).floor() as usize,
+            }
+        }
+
+        pub fn train(&self, map: &Map, model: Model, iterations: usize) -> Model {
+            let pool = Pool::new_batched(Arc::new(map.clone()), pool::distances);
+            let mut rng = thread_rng();
+            let mut mean = model.parameters();
+            let n = mean.len();
+            let nf = n as f64;
+            let lambda = self.population;
+            let mu = lambda / 2;
+
+            let mut weights: Vec<f64> = (0..mu).map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln()).collect();
+            let sum: f64 = weights.iter().sum();
+            for w in weights.iter_mut() {
+                *w /= sum;
+            }
+            let mueff = 1. / weights.iter().map(|w| w * w).sum::<f64>();
+
+            let cc = (4. + mueff / nf) / (nf + 4. + 2. * mueff / nf);
+            let cs = (mueff + 2.) / (nf + mueff + 5.);
+            let c1 = 2. / ((nf + 1.3).powi(2) + mueff);
+            let cmu = (1. - c1).min(2. * (mueff - 2. + 1. / mueff) / ((nf + 2.).powi(2) + mueff));
+            let damps = 1. + 2. * (((mueff - 1.) / (nf + 1.)).sqrt() - 1.).max(0.) + cs;
+            let chi_n = nf.sqrt() * (1. - 1. / (4. * nf) + 1. / (21. * nf * nf));
+
+            let mut sigma = self.sigma;
+            let mut pc: DVector<f64> = DVector::zeros(n);
+            let mut ps: DVector<f64> = DVector::zeros(n);
+            let mut c: DMatrix<f64> = DMatrix::identity(n, n);
+            let mut best = (model.clone(), 0);
+
+            for iteration in 0..iterations {
+                // C = B * diag(d^2) * B^T
+                let eigen = c.clone().symmetric_eigen();
+                let b = eigen.eigenvectors;
+                let d = eigen.eigenvalues.map(|v| v.max(1e-20).sqrt());
+                let inv_sqrt_c = &b * DMatrix::from_diagonal(&d.map(|v| 1. / v)) * b.transpose();
+
+                let mut ys = Vec::new();
+                let mut models = Vec::new();
+                for _ in 0..lambda {
+                    let z = gaussian(n, &mut rng);
+                    let y = &b * d.component_mul(&z);
+                    models.push(with_parameters(&model, &(&mean + &y * sigma)));
+                    ys.push(y);
+                }
+                let evaluated = pool.evaluate(models);
+                let mut order: Vec<usize> = (0..lambda).collect();
+                order.sort_by(|i, j| evaluated[*j].1.cmp(&evaluated[*i].1));
+                if evaluated[order[0]].1 >= best.1 {
+                    best = (evaluated[order[0]].0.clone(), evaluated[order[0]].1);
+                }
+
+                let mut y_w: DVector<f64> = DVector::zeros(n);
+                for (k, i) in order.iter().take(mu).enumerate() {
+                    y_w += &ys[*i] * weights[k];
+                }
+                mean += &y_w * sigma;
+
+                ps = &ps * (1. - cs) + &inv_sqrt_c * &y_w * (cs * (2. - cs) * mueff).sqrt();
+                let norm = ps.norm() / (1. - (1. - cs).powi(2 * (iteration as i32 + 1))).sqrt();
+                let hsig = if norm / chi_n < 1.4 + 2. / (nf + 1.) { 1. } else { 0. };
+                pc = &pc * (1. - cc) + &y_w * (hsig * (cc * (2. - cc) * mueff).sqrt());
+
+                let mut rank_mu: DMatrix<f64> = DMatrix::zeros(n, n);
+                for (k, i) in order.iter().take(mu).enumerate() {
+                    rank_mu += &ys[*i] * ys[*i].transpose() * weights[k];
+                }
+                c = &c * (1. - c1 - cmu)
+                    + (&pc * pc.transpose() + &c * ((1. - hsig) * cc * (2. - cc))) * c1
+                    + rank_mu * cmu;
+                sigma *= ((cs / damps) * (ps.norm() / chi_n - 1.)).exp();
+
+                println!("cma-es iteration {}: best d={}, sigma={:.4}", iteration,
+                         evaluated[order[0]].1, sigma);
+            }
+            return best.0;
+        }
+    }
+}
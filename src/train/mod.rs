@@ -0,0 +1,9 @@
+pub(crate) mod pool;
+pub(crate) mod species;
+pub(crate) mod novelty;
+pub(crate) mod es;
+pub(crate) mod cloning;
+pub(crate) mod curve;
+pub(crate) mod rl;
+pub(crate) mod dqn;
+pub(crate) mod pruning;
@@ -0,0 +1,192 @@
+pub mod novelty {
+    use crate::Map;
+    use crate::Model;
+    use crate::train::pool::pool::{self, Pool};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    // What is kept of an episode to compare the behaviour of two cars.
+    #[derive(Debug, Clone, Copy)]
+    pub enum Characterization {
+        FinalPosition,
+        // positions sampled at this many regular steps of the trajectory
+        Trace(usize),
+        // grid cells of this size visited by the car
+        Cells(f32),
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum Behaviour {
+        Point(f32, f32),
+        Trace(Vec<(f32, f32)>),
+        Cells(HashSet<(i32, i32)>),
+    }
+
+    impl Behaviour {
+        pub fn distance(&self, other: &Behaviour) -> f64 {
+            match (self, other) {
+                (Behaviour::Point(x1, y1), Behaviour::Point(x2, y2)) => {
+                    ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt() as f64
+                },
+                (Behaviour::Trace(a), Behaviour::Trace(b)) => {
+                    let mut sum = 0.;
+                    for (p, q) in a.iter().zip(b.iter()) {
+                        sum += ((p.0 - q.0).powi(2) + (p.1 - q.1).powi(2)).sqrt() as f64;
+                    }
+                    sum / a.len().max(1) as f64
+                },
+                (Behaviour::Cells(a), Behaviour::Cells(b)) => {
+                    a.symmetric_difference(b).count() as f64
+                },
+                _ => panic!("behaviours of different kinds"),
+            }
+        }
+    }
+
+    pub fn characterize(kind: Characterization, trajectory: &Vec<(f32, f32)>) -> Behaviour {
+        match kind {
+            Characterization::FinalPosition => {
+                let last = trajectory.last().cloned().unwrap_or((500.0, 500.0));
+                Behaviour::Point(last.0, last.1)
+            },
+            Characterization::Trace(samples) => {
+                let mut trace = Vec::new();
+                for i in 0..samples {
+                    // a car that died early stays where it crashed
+                    let step = (i + 1) * pool::MAX_STEPS / samples;
+                    let p = match trajectory.get(step) {
+                        Some(p) => *p,
+                        None => trajectory.last().cloned().unwrap_or((500.0, 500.0)),
+                    };
+                    trace.push(p);
+                }
+                Behaviour::Trace(trace)
+            },
+            Characterization::Cells(size) => {
+                let mut cells = HashSet::new();
+                for p in trajectory.iter() {
+                    cells.insert(((p.0 / size) as i32, (p.1 / size) as i32));
+                }
+                Behaviour::Cells(cells)
+            },
+        }
+    }
+
+    // distance driven and positions of the car at every step
+    pub fn evaluate(map: &Map, model: &Model) -> (usize, Vec<(f32, f32)>) {
+        let mut trajectory = Vec::new();
+        let car = pool::run_episode_with(map, model, |car| trajectory.push((car.x, car.y)));
+        return (car.d as usize, trajectory);
+    }
+
+    // Behaviours that were novel enough when they were found.
+    pub struct Archive {
+        pub behaviours: Vec<Behaviour>,
+        pub k: usize,
+        pub threshold: f64,
+    }
+
+    impl Archive {
+        pub fn new(k: usize, threshold: f64) -> Archive {
+            Archive {
+                behaviours: Vec::new(),
+                k: k,
+                threshold: threshold,
+            }
+        }
+
+        // mean distance to the k nearest neighbours in the population and the archive
+        pub fn novelty(&self, behaviour: &Behaviour, population: &Vec<Behaviour>) -> f64 {
+            let mut distances: Vec<f64> = Vec::new();
+            for b in population.iter().chain(self.behaviours.iter()) {
+                distances.push(behaviour.distance(b));
+            }
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // the behaviour is in the population: skip the distance to itself
+            let neighbours: Vec<&f64> = distances.iter().skip(1).take(self.k).collect();
+            if neighbours.is_empty() {
+                return 0.;
+            }
+            return neighbours.iter().map(|d| **d).sum::<f64>() / neighbours.len() as f64;
+        }
+
+        pub fn add(&mut self, behaviour: Behaviour, novelty: f64) -> bool {
+            if novelty > self.threshold {
+                self.behaviours.push(behaviour);
+                return true;
+            }
+            return false;
+        }
+    }
+
+    // Score of every individual: novelty blended with the task fitness, both
+    // scaled by their maximum in the population. `blend` = 0 is pure novelty
+    // search, 1 is the usual distance fitness.
+    pub fn score(novelty: &Vec<f64>, fitness: &Vec<usize>, blend: f64) -> Vec<f64> {
+        let max_n = novelty.iter().cloned().fold(0., f64::max).max(1e-9);
+        let max_f = fitness.iter().cloned().max().unwrap_or(0).max(1) as f64;
+        let mut result = Vec::new();
+        for (n, f) in novelty.iter().zip(fitness.iter()) {
+            result.push((1. - blend) * n / max_n + blend * *f as f64 / max_f);
+        }
+        return result;
+    }
+
+    // Same generations as the genetic training but the survivors are the
+    // most novel cars. Returns the models that drove the furthest.
+    pub fn train(map: &Map, model: Model, kind: Characterization, blend: f64) -> Vec<Model> {
+        let pool = Pool::new(Arc::new(map.clone()), evaluate);
+        let mut archive = Archive::new(15, 0.);
+        let mut champions: Vec<(Model, usize)> = Vec::new();
+
+        let mut models = Vec::new();
+        for _ in 0..500 {
+            models.push(model.copy_mut(1.0, 3.0));
+        }
+        for generation in 0..20 {
+            let evaluated = pool.evaluate(models);
+            let mut behaviours = Vec::new();
+            let mut fitness = Vec::new();
+            for e in evaluated.iter() {
+                behaviours.push(characterize(kind, &(e.1).1));
+                fitness.push((e.1).0);
+            }
+            let mut novelty = Vec::new();
+            for b in behaviours.iter() {
+                novelty.push(archive.novelty(b, &behaviours));
+            }
+            let scores = score(&novelty, &fitness, blend);
+
+            let mut order: Vec<usize> = (0..evaluated.len()).collect();
+            order.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap());
+            // the threshold follows the novelty of what is found: the 10%
+            // most novel cars are above it, whatever the blend
+            let mut by_novelty: Vec<usize> = (0..evaluated.len()).collect();
+            by_novelty.sort_by(|a, b| novelty[*b].partial_cmp(&novelty[*a]).unwrap());
+            archive.threshold = novelty[by_novelty[by_novelty.len() / 10]];
+            let mut added = 0;
+            for i in order.iter().take(8) {
+                if archive.add(behaviours[*i].clone(), novelty[*i]) {
+                    added += 1;
+                }
+            }
+            for (i, e) in evaluated.iter().enumerate() {
+                champions.push((e.0.clone(), fitness[i]));
+            }
+            champions.sort_by(|a, b| b.1.cmp(&a.1));
+            champions.truncate(8);
+            println!("novelty generation {}: best novelty={:.2}, best d={}, archive={} (+{})",
+                     generation, novelty[by_novelty[0]], champions[0].1, archive.behaviours.len(), added);
+
+            models = Vec::new();
+            for i in order.iter().take(8) {
+                let parent = &evaluated[*i].0;
+                models.push(parent.clone());
+                for _ in 0..100 {
+                    models.push(parent.copy_mut(0.05, 0.5));
+                }
+            }
+        }
+        return champions.into_iter().map(|c| c.0).collect();
+    }
+}
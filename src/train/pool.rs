@@ -0,0 +1,267 @@
+pub mod pool {
+    use crate::Map;
+    use crate::Model;
+    use crate::Car;
+    use crate::Controller;
+    use crate::model::model::Scratch;
+    use na::{DMatrix, Real};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+
+    // length of an evaluation episode
+    pub const MAX_STEPS: usize = 500;
+
+    // number of individuals a worker takes from the queue at once
+    const BATCH: usize = 16;
+
+    struct Job<T> {
+        start: usize,
+        items: Vec<T>,
+    }
+
+    // a batch that panicked gives back the message of the panic
+    type Done<T, R> = (usize, Result<Vec<(T, R)>, String>);
+
+    // how a worker evaluates its batch: item by item, or all at once
+    enum Evaluate<T, R> {
+        One(fn(&Map, &T) -> R),
+        Batch(fn(&Map, &[T]) -> Vec<R>),
+    }
+
+    // Persistent pool of workers sharing one map. Each worker pulls small
+    // batches from a common queue, so a thread stuck on long episodes does
+    // not hold back the others.
+    pub struct Pool<T, R> {
+        jobs: Option<mpsc::Sender<Job<T>>>,
+        done: mpsc::Receiver<Done<T, R>>,
+        workers: Vec<thread::JoinHandle<()>>,
+    }
+
+    impl<T: Send + 'static, R: Send + 'static> Pool<T, R> {
+        pub fn new(map: Arc<Map>, evaluate: fn(&Map, &T) -> R) -> Pool<T, R> {
+            let size = match thread::available_parallelism() {
+                Ok(n) => n.get(),
+                Err(_) => 1,
+            };
+            Pool::with_size(map, evaluate, size)
+        }
+
+        // `evaluate` gets the whole batch of a worker, so it can drive the
+        // cars of the batch in lockstep.
+        pub fn new_batched(map: Arc<Map>, evaluate: fn(&Map, &[T]) -> Vec<R>) -> Pool<T, R> {
+            let size = match thread::available_parallelism() {
+                Ok(n) => n.get(),
+                Err(_) => 1,
+            };
+            Pool::spawn(map, Evaluate::Batch(evaluate), size)
+        }
+
+        pub fn with_size(map: Arc<Map>, evaluate: fn(&Map, &T) -> R, size: usize) -> Pool<T, R> {
+            Pool::spawn(map, Evaluate::One(evaluate), size)
+        }
+
+        fn spawn(map: Arc<Map>, evaluate: Evaluate<T, R>, size: usize) -> Pool<T, R> {
+            let evaluate = Arc::new(evaluate);
+            let (tx_job, rx_job) = mpsc::channel::<Job<T>>();
+            let (tx_done, rx_done) = mpsc::channel();
+            let rx_job = Arc::new(Mutex::new(rx_job));
+            let mut workers = Vec::new();
+            for _ in 0..size.max(1) {
+                let rx_job = Arc::clone(&rx_job);
+                let tx_done = tx_done.clone();
+                let map = Arc::clone(&map);
+                let evaluate = Arc::clone(&evaluate);
+                workers.push(thread::spawn(move || {
+                    loop {
+                        let job = rx_job.lock().unwrap().recv();
+                        let job = match job {
+                            Ok(job) => job,
+                            // the pool was dropped
+                            Err(_) => return,
+                        };
+                        let items = job.items;
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            let mut result = Vec::new();
+                            match *evaluate {
+                                Evaluate::One(f) => {
+                                    for item in items {
+                                        let r = f(&map, &item);
+                                        result.push((item, r));
+                                    }
+                                },
+                                Evaluate::Batch(f) => {
+                                    let rs = f(&map, &items);
+                                    result.extend(items.into_iter().zip(rs));
+                                },
+                            }
+                            result
+                        }));
+                        let result = result.map_err(|e| panic_message(&*e));
+                        if tx_done.send((job.start, result)).is_err() {
+                            return;
+                        }
+                    }
+                }));
+            }
+            Pool {
+                jobs: Some(tx_job),
+                done: rx_done,
+                workers: workers,
+            }
+        }
+
+        pub fn size(&self) -> usize {
+            self.workers.len()
+        }
+
+        // Evaluate every item and give them back in the same order with their
+        // result. Panics when the evaluation of an item panicked in a worker.
+        pub fn evaluate(&self, items: Vec<T>) -> Vec<(T, R)> {
+            match self.try_evaluate(items) {
+                Ok(result) => result,
+                Err(e) => panic!("an evaluation failed in a worker: {}", e),
+            }
+        }
+
+        // Same as evaluate, the message of the first panic of a worker is
+        // returned instead. The items are lost then.
+        pub fn try_evaluate(&self, items: Vec<T>) -> Result<Vec<(T, R)>, String> {
+            let total = items.len();
+            let jobs = self.jobs.as_ref().unwrap();
+            let mut sent = 0;
+            let mut count = 0;
+            let mut batch = Vec::new();
+            for item in items {
+                batch.push(item);
+                if batch.len() == BATCH {
+                    let start = sent;
+                    sent += batch.len();
+                    jobs.send(Job { start: start, items: batch }).unwrap();
+                    count += 1;
+                    batch = Vec::new();
+                }
+            }
+            if !batch.is_empty() {
+                jobs.send(Job { start: sent, items: batch }).unwrap();
+                count += 1;
+            }
+
+            // every job answers, even after a failure, so that nothing is
+            // left in the channel for the next call
+            let mut parts = Vec::new();
+            let mut error = None;
+            for _ in 0..count {
+                let part: Done<T, R> = self.done.recv().expect("all the workers are dead");
+                match part.1 {
+                    Ok(r) => parts.push((part.0, r)),
+                    Err(e) => {
+                        if error.is_none() {
+                            error = Some(e);
+                        }
+                    },
+                }
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+            parts.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut result = Vec::with_capacity(total);
+            for part in parts {
+                result.extend(part.1);
+            }
+            return Ok(result);
+        }
+    }
+
+    impl<T, R> Drop for Pool<T, R> {
+        fn drop(&mut self) {
+            // closing the queue makes every worker return
+            self.jobs.take();
+            for worker in self.workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+    }
+
+    fn panic_message(e: &(dyn std::any::Any + Send)) -> String {
+        if let Some(s) = e.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = e.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    }
+
+    // The car crashed or stopped after `n` steps, the episode ends here
+    // whatever the time left.
+    pub fn stopped(car: &Car, n: usize) -> bool {
+        !car.alive || (n > 0 && car.v < 0.1)
+    }
+
+    // The car stopped or ran out of time, every episode ends on this.
+    pub fn episode_over(car: &Car, n: usize) -> bool {
+        stopped(car, n) || n > MAX_STEPS
+    }
+
+    // Drive one car alone until it crashes, stops or runs out of time.
+    pub fn run_episode<C: Controller>(map: &Map, model: &C) -> Car {
+        run_episode_with(map, model, |_| {})
+    }
+
+    // Same as run_episode, `on_step` sees the car after every physics tick.
+    pub fn run_episode_with<C: Controller, F: FnMut(&Car)>(map: &Map, model: &C, mut on_step: F) -> Car {
+        let mut car = Car::new(500.0, 500., 0.0);
+        let mut n = 0;
+        model.reset_state();
+        loop {
+            car.input(model);
+            car.update(map);
+            on_step(&car);
+            if episode_over(&car, n) {
+                return car;
+            }
+            n = n + 1;
+        }
+    }
+
+    // One car per model, all driven in lockstep with the same stopping
+    // rules as run_episode. The buffers of every car are reused from one
+    // step to the next, so dense models drive without allocating.
+    pub fn run_episodes<N: Real>(map: &Map, models: &[Model<N>]) -> Vec<Car>
+        where Model<N>: Controller {
+        let mut cars: Vec<Car> = models.iter().map(|_| Car::new(500.0, 500., 0.0)).collect();
+        let mut observations: Vec<DMatrix<N>> = models.iter().map(|_| DMatrix::zeros(0, 0)).collect();
+        let mut scratches: Vec<Scratch<N>> = models.iter().map(|m| m.scratch()).collect();
+        let mut running = vec![true; models.len()];
+        for m in models.iter() {
+            m.reset_state();
+        }
+        let mut n = 0;
+        while running.iter().any(|r| *r) {
+            for (i, car) in cars.iter_mut().enumerate() {
+                if !running[i] {
+                    continue;
+                }
+                car.input_with(&models[i], &mut observations[i], &mut scratches[i]);
+                car.update(map);
+                if episode_over(car, n) {
+                    running[i] = false;
+                }
+            }
+            n = n + 1;
+        }
+        return cars;
+    }
+
+    // fitness used by the genetic training: distance driven
+    pub fn distance(map: &Map, model: &Model) -> usize {
+        run_episode(map, model).d as usize
+    }
+
+    // distance of a whole batch, for Pool::new_batched
+    pub fn distances(map: &Map, models: &[Model]) -> Vec<usize> {
+        run_episodes(map, models).iter().map(|car| car.d as usize).collect()
+    }
+}
@@ -0,0 +1,129 @@
+pub mod pruning {
+    use crate::Map;
+    use crate::Model;
+    use crate::train::pool::pool::{self, Pool};
+    use std::sync::Arc;
+
+    pub struct Report {
+        pub sparsity_before: f64,
+        pub sparsity_after: f64,
+        pub fitness_before: usize,
+        pub fitness_after: usize,
+    }
+
+    pub fn report(map: &Map, before: &Model, after: &Model) -> Report {
+        Report {
+            sparsity_before: sparsity(before),
+            sparsity_after: sparsity(after),
+            fitness_before: pool::distance(map, before),
+            fitness_after: pool::distance(map, after),
+        }
+    }
+
+    // Indices in parameters() of the weights. The biases are never pruned.
+    fn weights(model: &Model) -> Vec<usize> {
+        let mut r = Vec::new();
+        for (name, range) in model.parameter_slices() {
+            if !name.rsplit('.').next().unwrap().starts_with('b') {
+                r.extend(range);
+            }
+        }
+        return r;
+    }
+
+    // part of the weights that are pruned
+    pub fn sparsity(model: &Model) -> f64 {
+        model.pruned().len() as f64 / weights(model).len().max(1) as f64
+    }
+
+    // The weights that are not pruned yet, smallest magnitude first.
+    fn candidates(model: &Model) -> Vec<usize> {
+        let parameters = model.parameters();
+        let pruned = model.pruned();
+        let mut r: Vec<usize> = weights(model).into_iter().filter(|i| pruned.binary_search(i).is_err()).collect();
+        r.sort_by(|a, b| parameters[*a].abs().partial_cmp(&parameters[*b].abs()).unwrap());
+        return r;
+    }
+
+    // Prune the weights of smallest magnitude until `fraction` of all the
+    // weights are pruned.
+    pub fn magnitude(model: &Model, fraction: f64) -> Model {
+        let mut model = model.clone();
+        let left = candidates(&model);
+        let pruned = model.pruned().len();
+        let target = (fraction * weights(&model).len() as f64).round() as usize;
+        for i in left.iter().take(target.saturating_sub(pruned)) {
+            model.prune(*i);
+        }
+        return model;
+    }
+
+    // Every round tries to prune each of the `batch` smallest weights alone,
+    // then prunes together those that cost less than `tolerance` of the
+    // fitness of the original model. Stops when no weight can go.
+    pub fn fitness_aware(map: &Map, model: &Model, tolerance: f64, batch: usize) -> Model {
+        let pool = Pool::new_batched(Arc::new(map.clone()), pool::distances);
+        let base = pool::distance(map, model);
+        let floor = (base as f64 * (1. - tolerance)).ceil() as usize;
+        let mut model = model.clone();
+        let mut round = 0;
+        loop {
+            let tried: Vec<usize> = candidates(&model).into_iter().take(batch).collect();
+            if tried.is_empty() {
+                return model;
+            }
+            let mut variants = Vec::new();
+            for i in tried.iter() {
+                let mut m = model.clone();
+                m.prune(*i);
+                variants.push(m);
+            }
+            let evaluated = pool.evaluate(variants);
+            let mut accepted: Vec<(usize, usize)> = tried.iter().zip(evaluated.iter())
+                .filter(|(_, e)| e.1 >= floor)
+                .map(|(i, e)| (*i, e.1))
+                .collect();
+            accepted.sort_by(|a, b| b.1.cmp(&a.1));
+
+            // the weights that can go alone may not all go together
+            loop {
+                if accepted.is_empty() {
+                    return model;
+                }
+                let mut m = model.clone();
+                for (i, _) in accepted.iter() {
+                    m.prune(*i);
+                }
+                let d = pool::distance(map, &m);
+                if d >= floor {
+                    model = m;
+                    println!("pruning round {}: {} weights pruned, sparsity={:.1}%, d={}", round,
+                             accepted.len(), sparsity(&model) * 100., d);
+                    break;
+                }
+                let half = accepted.len() / 2;
+                accepted.truncate(half);
+            }
+            round = round + 1;
+        }
+    }
+
+    // Evolve the remaining weights again, the pruned ones stay at zero.
+    pub fn reevolve(map: &Map, model: Model, generations: usize) -> Model {
+        let pool = Pool::new_batched(Arc::new(map.clone()), pool::distances);
+        let mut best = (model.clone(), pool::distance(map, &model));
+        for generation in 0..generations {
+            let mut children = Vec::new();
+            for _ in 0..200 {
+                children.push(best.0.copy_mut(0.05, 0.5));
+            }
+            for e in pool.evaluate(children) {
+                if e.1 > best.1 {
+                    best = e;
+                }
+            }
+            println!("re-evolution generation {}: d={}, sparsity={:.1}%", generation, best.1, sparsity(&best.0) * 100.);
+        }
+        return best.0;
+    }
+}
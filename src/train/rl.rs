@@ -0,0 +1,189 @@
+pub mod rl {
+    use crate::Map;
+    use crate::Model;
+    use crate::Car;
+    use crate::Controller;
+    use crate::env::car::car::OBSERVATION_SIZE;
+    use crate::model::model::{Activation, Init, Layer};
+    use crate::model::grad::grad::{Loss, Optimizer};
+    use crate::train::curve::curve::Curve;
+    use crate::train::pool::pool;
+    use na::{DMatrix, DVector};
+    use rand::distributions::StandardNormal;
+    use rand::prelude::*;
+
+    // the critic answers with tanh, its values are scaled by this
+    const VALUE_SCALE: f64 = 100.;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Method {
+        // Monte Carlo returns minus their mean
+        Reinforce,
+        // a critic model learns the value of the observations,
+        // advantage = r + gamma * V(s') - V(s)
+        ActorCritic,
+    }
+
+    struct Step {
+        observation: DVector<f64>,
+        mean: DVector<f64>,
+        action: DVector<f64>,
+        reward: f64,
+        // observation after the step
+        next: DVector<f64>,
+        // terminal state: crashed or stopped
+        done: bool,
+        // last step of the episode, terminal or stopped by the time limit
+        end: bool,
+    }
+
+    pub struct PolicyGradient {
+        pub method: Method,
+        // standard deviation of the gaussian policy around the output of the model
+        pub sigma: f64,
+        pub gamma: f64,
+        pub crash_penalty: f64,
+        // episodes played before every update
+        pub episodes: usize,
+        pub learning_rate: f64,
+    }
+
+    impl PolicyGradient {
+        pub fn new(method: Method) -> PolicyGradient {
+            PolicyGradient {
+                method: method,
+                sigma: 0.2,
+                gamma: 0.99,
+                crash_penalty: 50.,
+                episodes: 8,
+                learning_rate: 0.001,
+            }
+        }
+
+        // One episode with actions drawn around the output of the policy.
+        // Reward of a step: the distance driven, minus a penalty for crashing.
+        fn rollout(&self, map: &Map, policy: &Model, rng: &mut ThreadRng) -> (Vec<Step>, Car) {
+            let mut car = Car::new(500.0, 500., 0.0);
+            let mut steps = Vec::new();
+            let mut n = 0;
+            policy.reset_state();
+            loop {
+                let observation = car.observe(policy);
+                let mean = policy.get_result(&observation);
+                let action = DVector::from_fn(mean.len(), |i, _| mean[i] + self.sigma * rng.sample::<f64, _>(StandardNormal));
+                car.set_action(action[0], action[1]);
+                let d = car.d;
+                car.update(map);
+                let mut reward = (car.d - d) as f64;
+                if !car.alive {
+                    reward -= self.crash_penalty;
+                }
+                // a car stopped by the time limit is not a terminal state
+                let done = pool::stopped(&car, n);
+                let end = pool::episode_over(&car, n);
+                steps.push(Step {
+                    observation: DVector::from_column_slice(observation.as_slice()),
+                    mean: DVector::from_column_slice(mean.as_slice()),
+                    action: action,
+                    reward: reward,
+                    next: DVector::from_column_slice(car.observe(policy).as_slice()),
+                    done: done,
+                    end: end,
+                });
+                if end {
+                    return (steps, car);
+                }
+                n = n + 1;
+            }
+        }
+
+        pub fn train(&self, map: &Map, policy: Model, iterations: usize, curve: &mut Curve) -> Model {
+            let mut rng = thread_rng();
+            let mut policy = policy;
+            let mut optimizer = Optimizer::adam(self.learning_rate);
+            let mut critic = Model::new();
+            let inputs = policy.input_size().unwrap_or(OBSERVATION_SIZE);
+            critic.add_layer(Layer::new_init(inputs, 16, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            critic.add_layer(Layer::new_init(16, 1, Activation::Tanh, Init::XavierUniform, rand::random())).unwrap();
+            let mut critic_optimizer = Optimizer::adam(self.learning_rate * 3.);
+            let mut best = (policy.clone(), 0);
+
+            for iteration in 0..iterations {
+                let mut steps = Vec::new();
+                let mut distances = Vec::new();
+                for _ in 0..self.episodes {
+                    let (s, car) = self.rollout(map, &policy, &mut rng);
+                    steps.extend(s);
+                    distances.push(car.d as f64);
+                }
+                let observations: Vec<DVector<f64>> = steps.iter().map(|s| s.observation.clone()).collect();
+                let observations = DMatrix::from_columns(&observations);
+
+                // discounted return of every step, up to the end of its episode
+                let mut returns = vec![0.; steps.len()];
+                let mut g = 0.;
+                for (i, s) in steps.iter().enumerate().rev() {
+                    if s.end {
+                        g = 0.;
+                    }
+                    g = s.reward + self.gamma * g;
+                    returns[i] = g;
+                }
+
+                let mut advantages = vec![0.; steps.len()];
+                match self.method {
+                    Method::Reinforce => {
+                        let baseline = returns.iter().sum::<f64>() / returns.len() as f64;
+                        for i in 0..steps.len() {
+                            advantages[i] = returns[i] - baseline;
+                        }
+                    },
+                    Method::ActorCritic => {
+                        let values = &critic.forward_trace(&observations).last().unwrap().1 * VALUE_SCALE;
+                        let nexts: Vec<DVector<f64>> = steps.iter().map(|s| s.next.clone()).collect();
+                        let next_values = critic.get_result(&DMatrix::from_columns(&nexts)) * VALUE_SCALE;
+                        let mut targets = DMatrix::zeros(1, steps.len());
+                        for (i, s) in steps.iter().enumerate() {
+                            // the episodes cut by the time limit bootstrap too
+                            let next = if s.done { 0. } else { next_values[i] };
+                            targets[i] = (s.reward + self.gamma * next) / VALUE_SCALE;
+                            advantages[i] = s.reward + self.gamma * next - values[i];
+                        }
+                        critic.train_batch(&observations, &targets, Loss::Mse, &mut critic_optimizer);
+                    },
+                }
+                let mean = advantages.iter().sum::<f64>() / advantages.len() as f64;
+                let std = (advantages.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / advantages.len() as f64).sqrt();
+                for a in advantages.iter_mut() {
+                    *a = (*a - mean) / (std + 1e-8);
+                }
+
+                // loss = -mean(advantage * log pi(action)), for a gaussian
+                // d log pi / d mean = (action - mean) / sigma^2
+                let trace = policy.forward_trace(&observations);
+                let mut grad_output = DMatrix::zeros(2, steps.len());
+                for (i, s) in steps.iter().enumerate() {
+                    for j in 0..2 {
+                        grad_output[(j, i)] = -advantages[i] * (s.action[j] - s.mean[j])
+                            / (self.sigma * self.sigma) / steps.len() as f64;
+                    }
+                }
+                let gradient = policy.backward(&observations, &trace, grad_output);
+                let mut parameters = policy.parameters();
+                optimizer.step(&mut parameters, &gradient);
+                policy.set_parameters(&parameters);
+
+                let mean_d = distances.iter().sum::<f64>() / distances.len() as f64;
+                let best_d = distances.iter().cloned().fold(0., f64::max);
+                curve.push(self.episodes, mean_d, best_d);
+                let d = pool::distance(map, &policy);
+                if d >= best.1 {
+                    best = (policy.clone(), d);
+                }
+                println!("{:?} iteration {}: mean d={:.1}, best d={:.1}, greedy d={}",
+                         self.method, iteration, mean_d, best_d, d);
+            }
+            return best.0;
+        }
+    }
+}
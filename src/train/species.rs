@@ -0,0 +1,114 @@
+pub mod species {
+    use crate::Model;
+
+    pub struct Species {
+        pub representative: Model,
+        pub members: Vec<(Model, usize)>,
+    }
+
+    impl Species {
+        pub fn len(&self) -> usize {
+            self.members.len()
+        }
+
+        pub fn best(&self) -> usize {
+            self.members.iter().map(|m| m.1).max().unwrap_or(0)
+        }
+
+        // fitness sharing: every member's fitness is divided by the size of its
+        // species, so the sum is the mean fitness of the species
+        pub fn shared_fitness(&self) -> f64 {
+            let mut sum = 0.;
+            for m in self.members.iter() {
+                sum += m.1 as f64 / self.members.len() as f64;
+            }
+            return sum;
+        }
+    }
+
+    // Put every model in the first species whose representative is closer
+    // than `threshold`, or in a new species if none is.
+    pub fn speciate(population: Vec<(Model, usize)>, threshold: f64) -> Vec<Species> {
+        let mut species: Vec<Species> = Vec::new();
+        for m in population {
+            let mut found = None;
+            for (i, s) in species.iter().enumerate() {
+                if s.representative.distance(&m.0) < threshold {
+                    found = Some(i);
+                    break;
+                }
+            }
+            match found {
+                Some(i) => species[i].members.push(m),
+                None => {
+                    species.push(Species {
+                        representative: m.0.clone(),
+                        members: vec![m],
+                    });
+                },
+            }
+        }
+        for s in species.iter_mut() {
+            s.members.sort_by(|a, b| b.1.cmp(&a.1));
+        }
+        species.sort_by(|a, b| b.best().cmp(&a.best()));
+        return species;
+    }
+
+    // Number of survivors given to each species, proportional to its shared
+    // fitness. The best species always get at least one survivor.
+    pub fn quotas(species: &Vec<Species>, num: usize) -> Vec<usize> {
+        let mut quotas = vec![0; species.len()];
+        let total: f64 = species.iter().map(|s| s.shared_fitness()).sum();
+        let mut given = 0;
+        for (i, s) in species.iter().enumerate() {
+            if given >= num {
+                break;
+            }
+            let part = if total > 0. {
+                (s.shared_fitness() / total * num as f64).floor() as usize
+            } else {
+                0
+            };
+            quotas[i] = part.max(1).min(s.len()).min(num - given);
+            given += quotas[i];
+        }
+        // give what is left to the best species that still have members
+        while given < num {
+            let mut changed = false;
+            for (i, s) in species.iter().enumerate() {
+                if given < num && quotas[i] < s.len() {
+                    quotas[i] += 1;
+                    given += 1;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        return quotas;
+    }
+
+    pub fn report(generation: usize, species: &Vec<Species>) {
+        let sizes: Vec<usize> = species.iter().map(|s| s.len()).collect();
+        let bests: Vec<usize> = species.iter().map(|s| s.best()).collect();
+        println!("generation {}: {} species, sizes={:?}, bests={:?}",
+                 generation, species.len(), sizes, bests);
+    }
+
+    // Keep `num` survivors spread over the species instead of the `num` bests.
+    pub fn select(generation: usize, population: Vec<(Model, usize)>, threshold: f64, num: usize) -> Vec<(Model, usize)> {
+        let species = speciate(population, threshold);
+        report(generation, &species);
+        let quotas = quotas(&species, num);
+        let mut survivors = Vec::new();
+        for (s, quota) in species.into_iter().zip(quotas.into_iter()) {
+            for m in s.members.into_iter().take(quota) {
+                survivors.push(m);
+            }
+        }
+        survivors.sort_by(|a, b| b.1.cmp(&a.1));
+        return survivors;
+    }
+}